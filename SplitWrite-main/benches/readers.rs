@@ -0,0 +1,365 @@
+//! Compares `splitwrite` against lock-based and clone-on-write alternatives.
+//!
+//! Every contender guards the same data, either a single counter or a map, and is measured on:
+//!
+//! - `read`: the time for each of N concurrent readers to do one read.
+//! - `write`: the time for one write to become visible to readers, while N readers keep reading.
+//!   For `splitwrite` this includes the publish.
+//! - `mixed`: four readers and one writer sharing the data, with writes making up a given
+//!   fraction of all operations. The time is per read.
+//!
+//! Run with `cargo bench`, or e.g. `cargo bench -- read/map` for a subset.
+
+use arc_swap::ArcSwap;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use splitwrite::Absorb;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const READERS: [usize; 4] = [1, 2, 4, 8];
+const WRITE_RATIOS: [f64; 3] = [0.001, 0.01, 0.1];
+const MIXED_READERS: usize = 4;
+const KEYS: u64 = 1024;
+
+/// The data being shared.
+trait Data: Clone + Default + Send + Sync + 'static {
+    const NAME: &'static str;
+
+    fn read(&self, key: u64) -> u64;
+
+    fn write(&mut self, key: u64);
+}
+
+impl Data for u64 {
+    const NAME: &'static str = "counter";
+
+    fn read(&self, _: u64) -> u64 {
+        *self
+    }
+
+    fn write(&mut self, _: u64) {
+        *self += 1;
+    }
+}
+
+impl Data for HashMap<u64, u64> {
+    const NAME: &'static str = "map";
+
+    fn read(&self, key: u64) -> u64 {
+        self.get(&(key % KEYS)).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, key: u64) {
+        *self.entry(key % KEYS).or_insert(0) += 1;
+    }
+}
+
+/// A way of sharing `D` between one writer and many readers.
+trait Contender<D: Data>: Send + Sized + 'static {
+    const NAME: &'static str;
+
+    type Reader: Send + 'static;
+
+    fn new(data: D) -> Self;
+
+    fn reader(&self) -> Self::Reader;
+
+    fn read_key(reader: &Self::Reader, key: u64) -> u64;
+
+    /// Make a write that readers will observe from now on.
+    fn write_key(&mut self, key: u64);
+}
+
+#[derive(Clone, Default)]
+struct Store<D>(D);
+
+struct Write(u64);
+
+impl<D: Data> Absorb<Write> for Store<D> {
+    fn absorb_first(&mut self, operation: &mut Write, _: &Self) {
+        self.0.write(operation.0);
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+impl<D: Data> Contender<D> for splitwrite::WriteHandle<Store<D>, Write> {
+    const NAME: &'static str = "splitwrite";
+
+    type Reader = splitwrite::ReadHandle<Store<D>>;
+
+    fn new(data: D) -> Self {
+        let (mut w, _) = splitwrite::new_from_empty(Store(data));
+        w.publish();
+        w
+    }
+
+    fn reader(&self) -> Self::Reader {
+        (**self).clone()
+    }
+
+    fn read_key(reader: &Self::Reader, key: u64) -> u64 {
+        reader.enter().unwrap().0.read(key)
+    }
+
+    fn write_key(&mut self, key: u64) {
+        self.append(Write(key)).publish();
+    }
+}
+
+impl<D: Data> Contender<D> for Arc<RwLock<D>> {
+    const NAME: &'static str = "std::RwLock";
+
+    type Reader = Self;
+
+    fn new(data: D) -> Self {
+        Arc::new(RwLock::new(data))
+    }
+
+    fn reader(&self) -> Self {
+        Arc::clone(self)
+    }
+
+    fn read_key(reader: &Self, key: u64) -> u64 {
+        reader.read().unwrap().read(key)
+    }
+
+    fn write_key(&mut self, key: u64) {
+        self.write().unwrap().write(key);
+    }
+}
+
+impl<D: Data> Contender<D> for Arc<Mutex<D>> {
+    const NAME: &'static str = "std::Mutex";
+
+    type Reader = Self;
+
+    fn new(data: D) -> Self {
+        Arc::new(Mutex::new(data))
+    }
+
+    fn reader(&self) -> Self {
+        Arc::clone(self)
+    }
+
+    fn read_key(reader: &Self, key: u64) -> u64 {
+        reader.lock().unwrap().read(key)
+    }
+
+    fn write_key(&mut self, key: u64) {
+        self.lock().unwrap().write(key);
+    }
+}
+
+impl<D: Data> Contender<D> for Arc<parking_lot::RwLock<D>> {
+    const NAME: &'static str = "parking_lot::RwLock";
+
+    type Reader = Self;
+
+    fn new(data: D) -> Self {
+        Arc::new(parking_lot::RwLock::new(data))
+    }
+
+    fn reader(&self) -> Self {
+        Arc::clone(self)
+    }
+
+    fn read_key(reader: &Self, key: u64) -> u64 {
+        reader.read().read(key)
+    }
+
+    fn write_key(&mut self, key: u64) {
+        self.write().write(key);
+    }
+}
+
+/// The clone-on-write baseline: readers load an `Arc` to an immutable snapshot, and the
+/// writer clones the whole snapshot for every write.
+impl<D: Data> Contender<D> for Arc<ArcSwap<D>> {
+    const NAME: &'static str = "arc-swap";
+
+    type Reader = Self;
+
+    fn new(data: D) -> Self {
+        Arc::new(ArcSwap::from_pointee(data))
+    }
+
+    fn reader(&self) -> Self {
+        Arc::clone(self)
+    }
+
+    fn read_key(reader: &Self, key: u64) -> u64 {
+        reader.load().read(key)
+    }
+
+    fn write_key(&mut self, key: u64) {
+        let mut next = D::clone(&self.load());
+        next.write(key);
+        self.store(Arc::new(next));
+    }
+}
+
+fn initial<D: Data>() -> D {
+    let mut data = D::default();
+    for key in 0..KEYS {
+        data.write(key);
+    }
+    data
+}
+
+/// Have each of `readers` read `iters` times on its own thread, and return how long the
+/// slowest took.
+fn timed_reads<D: Data, C: Contender<D>>(readers: Vec<C::Reader>, iters: u64) -> Duration {
+    let start = Arc::new(Barrier::new(readers.len()));
+    let threads: Vec<_> = readers
+        .into_iter()
+        .map(|reader| {
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                let began = Instant::now();
+                for key in 0..iters {
+                    black_box(C::read_key(&reader, key));
+                }
+                began.elapsed()
+            })
+        })
+        .collect();
+    threads
+        .into_iter()
+        .map(|t| t.join().unwrap())
+        .max()
+        .unwrap_or_default()
+}
+
+/// Readers that keep reading in the background until dropped.
+struct Background {
+    stop: Arc<AtomicBool>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl Background {
+    fn start<D: Data, C: Contender<D>>(c: &C, readers: usize) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let threads = (0..readers)
+            .map(|_| {
+                let reader = c.reader();
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || {
+                    let mut key = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        black_box(C::read_key(&reader, key));
+                        key += 1;
+                    }
+                })
+            })
+            .collect();
+        Self { stop, threads }
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for t in self.threads.drain(..) {
+            t.join().unwrap();
+        }
+    }
+}
+
+fn read<D: Data, C: Contender<D>>(crit: &mut Criterion) {
+    let mut group = crit.benchmark_group(format!("read/{}", D::NAME));
+    let c = C::new(initial::<D>());
+    for readers in READERS {
+        group.bench_with_input(BenchmarkId::new(C::NAME, readers), &readers, |b, &n| {
+            b.iter_custom(|iters| timed_reads::<D, C>((0..n).map(|_| c.reader()).collect(), iters))
+        });
+    }
+    group.finish();
+}
+
+fn write<D: Data, C: Contender<D>>(crit: &mut Criterion) {
+    let mut group = crit.benchmark_group(format!("write/{}", D::NAME));
+    for readers in READERS {
+        let mut c = C::new(initial::<D>());
+        let background = Background::start(&c, readers);
+        group.bench_with_input(BenchmarkId::new(C::NAME, readers), &readers, |b, _| {
+            let mut key = 0;
+            b.iter(|| {
+                c.write_key(key);
+                key += 1;
+            })
+        });
+        drop(background);
+    }
+    group.finish();
+}
+
+fn mixed<D: Data, C: Contender<D>>(crit: &mut Criterion) {
+    let mut group = crit.benchmark_group(format!("mixed/{}", D::NAME));
+    for ratio in WRITE_RATIOS {
+        let id = BenchmarkId::new(C::NAME, format!("{}%", ratio * 100.0));
+        let mut c = Some(C::new(initial::<D>()));
+        group.bench_function(id, |b| {
+            b.iter_custom(|iters| {
+                let mut writer = c.take().expect("the writer is put back after every run");
+                let readers = (0..MIXED_READERS).map(|_| writer.reader()).collect();
+                let reads = iters * MIXED_READERS as u64;
+                let writes = (reads as f64 * ratio / (1.0 - ratio)).round() as u64;
+
+                let began = Instant::now();
+                let writing = thread::spawn(move || {
+                    for key in 0..writes {
+                        writer.write_key(key);
+                    }
+                    writer
+                });
+                timed_reads::<D, C>(readers, iters);
+                c = Some(writing.join().unwrap());
+                began.elapsed()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn contenders<D: Data>(c: &mut Criterion) {
+    read::<D, splitwrite::WriteHandle<Store<D>, Write>>(c);
+    read::<D, Arc<RwLock<D>>>(c);
+    read::<D, Arc<parking_lot::RwLock<D>>>(c);
+    read::<D, Arc<Mutex<D>>>(c);
+    read::<D, Arc<ArcSwap<D>>>(c);
+
+    write::<D, splitwrite::WriteHandle<Store<D>, Write>>(c);
+    write::<D, Arc<RwLock<D>>>(c);
+    write::<D, Arc<parking_lot::RwLock<D>>>(c);
+    write::<D, Arc<Mutex<D>>>(c);
+    write::<D, Arc<ArcSwap<D>>>(c);
+
+    mixed::<D, splitwrite::WriteHandle<Store<D>, Write>>(c);
+    mixed::<D, Arc<RwLock<D>>>(c);
+    mixed::<D, Arc<parking_lot::RwLock<D>>>(c);
+    mixed::<D, Arc<Mutex<D>>>(c);
+    mixed::<D, Arc<ArcSwap<D>>>(c);
+}
+
+fn benches(c: &mut Criterion) {
+    contenders::<u64>(c);
+    contenders::<HashMap<u64, u64>>(c);
+}
+
+criterion_group! {
+    name = all;
+    config = Criterion::default()
+        .sample_size(20)
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(2));
+    targets = benches
+}
+criterion_main!(all);
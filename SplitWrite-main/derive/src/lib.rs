@@ -0,0 +1,184 @@
+//! Derive macro for [`splitwrite::Absorb`].
+//!
+//! Use it through the `derive` feature of `splitwrite` rather than depending on this crate
+//! directly.
+//!
+//! [`splitwrite::Absorb`]: https://docs.rs/splitwrite/*/splitwrite/trait.Absorb.html
+#![warn(missing_docs, rust_2018_idioms, missing_debug_implementations)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Ident, Path, Type};
+
+/// Implement `Absorb<Op>` for a data type, given an operation enum `Op`.
+///
+/// The enum must name the data type it applies to with `#[absorb(target = Type)]`. Every
+/// variant is applied by calling a method on the target with a shared reference to each of the
+/// variant's fields, in declaration order. By default the method is the variant name in
+/// `snake_case` on the target type; `#[absorb(apply = path::to::function)]` on a variant calls
+/// a different function instead.
+///
+/// The generated impl applies operations to both copies through the same method, so the two
+/// copies cannot diverge because of a mismatch between `absorb_first` and `absorb_second`. It
+/// implements `sync_with` by cloning, which requires the target to implement `Clone`, and drops
+/// both copies normally.
+///
+/// ```
+/// use splitwrite::Absorb;
+///
+/// #[derive(Clone, Default)]
+/// struct Counter(i64);
+///
+/// impl Counter {
+///     fn add(&mut self, n: &i64) {
+///         self.0 += n;
+///     }
+///
+///     fn reset(&mut self) {
+///         self.0 = 0;
+///     }
+/// }
+///
+/// fn set(counter: &mut Counter, value: &i64) {
+///     counter.0 = *value;
+/// }
+///
+/// #[derive(Absorb)]
+/// #[absorb(target = Counter)]
+/// enum CounterOp {
+///     Add(i64),
+///     Reset,
+///     #[absorb(apply = set)]
+///     Set { value: i64 },
+/// }
+///
+/// let (mut w, r) = splitwrite::new::<Counter, CounterOp>();
+/// w.append(CounterOp::Add(2)).append(CounterOp::Add(3));
+/// w.publish();
+/// assert_eq!(r.enter().unwrap().0, 5);
+/// ```
+#[proc_macro_derive(Absorb, attributes(absorb))]
+pub fn derive_absorb(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "Absorb can only be derived for operation enums",
+            ))
+        }
+    };
+
+    let target = enum_target(&input.attrs)?.ok_or_else(|| {
+        syn::Error::new(
+            input.ident.span(),
+            "missing `#[absorb(target = Type)]` naming the type operations apply to",
+        )
+    })?;
+
+    let op = &input.ident;
+    let mut arms = Vec::with_capacity(data.variants.len());
+    for variant in &data.variants {
+        let name = &variant.ident;
+        let apply = match variant_apply(&variant.attrs)? {
+            Some(path) => quote!(#path),
+            None => {
+                let method = Ident::new(&snake_case(&name.to_string()), name.span());
+                quote!(<#target>::#method)
+            }
+        };
+
+        let bindings: Vec<_> = (0..variant.fields.len())
+            .map(|i| format_ident!("__field{}", i))
+            .collect();
+        let pattern = match &variant.fields {
+            Fields::Named(fields) => {
+                let names = fields.named.iter().map(|f| &f.ident);
+                quote!(#op::#name { #(#names: #bindings),* })
+            }
+            Fields::Unnamed(_) => quote!(#op::#name(#(#bindings),*)),
+            Fields::Unit => quote!(#op::#name),
+        };
+        arms.push(quote! {
+            #pattern => #apply(self, #(&*#bindings),*),
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::splitwrite::Absorb<#op #ty_generics> for #target #where_clause {
+            fn absorb_first(&mut self, operation: &mut #op #ty_generics, _: &Self) {
+                match operation {
+                    #(#arms)*
+                }
+            }
+
+            fn absorb_second(&mut self, mut operation: #op #ty_generics, other: &Self) {
+                Self::absorb_first(self, &mut operation, other)
+            }
+
+            fn drop_first(self: ::std::boxed::Box<Self>) {}
+
+            fn drop_second(self: ::std::boxed::Box<Self>) {}
+
+            fn sync_with(&mut self, first: &Self) {
+                *self = ::std::clone::Clone::clone(first);
+            }
+        }
+    })
+}
+
+/// Find the `#[absorb(target = Type)]` attribute on the enum.
+fn enum_target(attrs: &[Attribute]) -> syn::Result<Option<Type>> {
+    let mut target = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("absorb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("target") {
+                target = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `target = Type`"))
+            }
+        })?;
+    }
+    Ok(target)
+}
+
+/// Find the `#[absorb(apply = path)]` attribute on a variant.
+fn variant_apply(attrs: &[Attribute]) -> syn::Result<Option<Path>> {
+    let mut apply = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("absorb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("apply") {
+                apply = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `apply = path::to::function`"))
+            }
+        })?;
+    }
+    Ok(apply)
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
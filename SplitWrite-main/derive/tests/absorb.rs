@@ -0,0 +1,105 @@
+use splitwrite::Absorb;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Inventory {
+    items: BTreeMap<String, u32>,
+}
+
+impl Inventory {
+    fn restock(&mut self, item: &str, count: &u32) {
+        *self.items.entry(item.to_string()).or_default() += count;
+    }
+
+    fn sell(&mut self, item: &String) {
+        if let Some(count) = self.items.get_mut(item) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    fn clear_out(&mut self) {
+        self.items.clear();
+    }
+}
+
+fn discontinue(inventory: &mut Inventory, item: &String) {
+    inventory.items.remove(item);
+}
+
+#[derive(Absorb)]
+#[absorb(target = Inventory)]
+enum InventoryOp {
+    Restock {
+        item: &'static str,
+        count: u32,
+    },
+    Sell(String),
+    #[absorb(apply = discontinue)]
+    Discontinue(String),
+    ClearOut,
+}
+
+#[test]
+fn both_copies_agree() {
+    let (mut w, r) = splitwrite::new::<Inventory, InventoryOp>();
+    w.append(InventoryOp::Restock {
+        item: "apple",
+        count: 3,
+    });
+    w.publish();
+
+    w.append(InventoryOp::Sell("apple".to_string()));
+    w.append(InventoryOp::Restock {
+        item: "pear",
+        count: 1,
+    });
+    w.publish();
+    w.append(InventoryOp::Discontinue("pear".to_string()));
+    w.publish();
+
+    let expected = Inventory {
+        items: [("apple".to_string(), 2)].into_iter().collect(),
+    };
+    assert_eq!(*r.enter().unwrap(), expected);
+
+    // the next publish swaps in the other copy, which must have ended up in the same state.
+    w.publish();
+    assert_eq!(*r.enter().unwrap(), expected);
+
+    w.append(InventoryOp::ClearOut);
+    w.publish();
+    w.publish();
+    assert!(r.enter().unwrap().items.is_empty());
+}
+
+#[derive(Clone, Default)]
+struct Log<T> {
+    entries: Vec<T>,
+}
+
+impl<T: Clone> Log<T> {
+    fn push(&mut self, entry: &T) {
+        self.entries.push(entry.clone());
+    }
+}
+
+#[derive(Absorb)]
+#[absorb(target = Log<T>)]
+enum LogOp<T: Clone> {
+    Push(T),
+}
+
+#[test]
+fn generic_target() {
+    let (mut w, r) = splitwrite::new::<Log<u8>, LogOp<u8>>();
+    w.append(LogOp::Push(1)).append(LogOp::Push(2));
+    w.publish();
+    w.append(LogOp::Push(3));
+    w.publish();
+    w.publish();
+    assert_eq!(r.enter().unwrap().entries, [1, 2, 3]);
+
+    let mut a = Log::default();
+    a.sync_with(&r.enter().unwrap());
+    assert_eq!(a.entries, [1, 2, 3]);
+}
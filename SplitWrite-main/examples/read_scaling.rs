@@ -0,0 +1,77 @@
+//! Measures how read throughput scales with the number of reader threads.
+//!
+//! Run with `cargo run --release --example read_scaling [seconds per step]`. Each step runs
+//! twice as many readers as the last, up to the number of available cores, while a writer
+//! publishes a small change every millisecond. Per-reader throughput should stay roughly flat
+//! as readers are added.
+
+use splitwrite::{Applied, Apply};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let step = std::env::args()
+        .nth(1)
+        .map(|s| Duration::from_secs_f64(s.parse().expect("seconds per step")))
+        .unwrap_or(Duration::from_secs(1));
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+
+    println!(
+        "{:>8} {:>16} {:>16}",
+        "readers", "reads/s", "reads/s/reader"
+    );
+    let mut readers = 1;
+    while readers <= cores {
+        let reads = run(readers, step);
+        let per_second = reads as f64 / step.as_secs_f64();
+        println!(
+            "{:>8} {:>16.0} {:>16.0}",
+            readers,
+            per_second,
+            per_second / readers as f64
+        );
+        readers *= 2;
+    }
+}
+
+/// Run `readers` reader threads for `duration`, and return how many reads they made in total.
+fn run(readers: usize, duration: Duration) -> u64 {
+    let (mut w, r) = splitwrite::new::<Applied<[u64; 8]>, Apply<[u64; 8]>>();
+    w.publish();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let start = Arc::new(Barrier::new(readers + 1));
+    let threads: Vec<_> = (0..readers)
+        .map(|_| {
+            let r = r.factory();
+            let stop = Arc::clone(&stop);
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                let r = r.handle();
+                let mut reads = 0u64;
+                let mut sum = 0u64;
+                start.wait();
+                while !stop.load(Ordering::Relaxed) {
+                    for _ in 0..64 {
+                        sum = sum.wrapping_add(r.enter().unwrap()[reads as usize % 8]);
+                        reads += 1;
+                    }
+                }
+                std::hint::black_box(sum);
+                reads
+            })
+        })
+        .collect();
+
+    start.wait();
+    let began = Instant::now();
+    while began.elapsed() < duration {
+        w.apply(|data| data[0] += 1);
+        w.publish();
+        thread::sleep(Duration::from_millis(1));
+    }
+    stop.store(true, Ordering::Relaxed);
+    threads.into_iter().map(|t| t.join().unwrap()).sum()
+}
@@ -1,4 +1,9 @@
-
+//! Types for sharing values between the copies of the data without cloning them.
+//!
+//! A value wrapped in [`Aliased`] can be bit-copied into every copy with
+//! [`Aliased::alias`]. The copies that should never drop the value use a [`DropBehavior`] with
+//! `DO_DROP = false`, and the last copy to let go of it switches to one with `DO_DROP = true`
+//! through [`Aliased::change_drop`]. See the `tests/deque.rs` test for a complete example.
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ops::Deref;
@@ -6,11 +11,27 @@ use std::ops::Deref;
 #[allow(unused_imports)]
 use crate::Absorb;
 
+/// Dictates whether an [`Aliased`] drops its inner value when it is itself dropped.
 pub trait DropBehavior {
-  
+    /// If true, the wrapped value is dropped along with the [`Aliased`].
     const DO_DROP: bool;
 }
 
+/// A [`DropBehavior`] for aliases that must never drop the value they wrap.
+#[derive(Debug)]
+pub struct NoDrop;
+impl DropBehavior for NoDrop {
+    const DO_DROP: bool = false;
+}
+
+/// A [`DropBehavior`] for the one alias that is responsible for dropping the value.
+#[derive(Debug)]
+pub struct DoDrop;
+impl DropBehavior for DoDrop {
+    const DO_DROP: bool = true;
+}
+
+/// A `T` that may be bit-copied into several places, only one of which drops it.
 #[repr(transparent)]
 pub struct Aliased<T, D>
 where
@@ -27,14 +48,20 @@ impl<T, D> Aliased<T, D>
 where
     D: DropBehavior,
 {
+    /// Create a bitwise copy of the wrapped value.
+    ///
+    /// # Safety
+    ///
+    /// At most one of the aliases may be dropped with a [`DropBehavior`] that drops the value,
+    /// and only once no other alias is accessed anymore.
     pub unsafe fn alias(&self) -> Self {
-      
         Aliased {
             aliased: std::ptr::read(&self.aliased),
             drop_behavior: PhantomData,
             _no_auto_send: PhantomData,
         }
     }
+    /// Wrap a value so that it can be aliased.
     pub fn from(t: T) -> Self {
         Self {
             aliased: MaybeUninit::new(t),
@@ -43,6 +70,12 @@ where
         }
     }
 
+    /// Change the [`DropBehavior`] of this alias.
+    ///
+    /// # Safety
+    ///
+    /// Switching to a behavior that drops the value is only safe if this is the last alias
+    /// that will be accessed.
     pub unsafe fn change_drop<D2: DropBehavior>(self) -> Aliased<T, D2> {
         Aliased {
             // safety:
@@ -72,7 +105,6 @@ where
 {
     fn drop(&mut self) {
         if D::DO_DROP {
-          
             unsafe { std::ptr::drop_in_place(self.aliased.as_mut_ptr()) }
         }
     }
@@ -83,7 +115,6 @@ where
     D: DropBehavior,
 {
     fn as_ref(&self) -> &T {
-        
         unsafe { &*self.aliased.as_ptr() }
     }
 }
@@ -0,0 +1,151 @@
+use crate::{Absorb, Preview, WriteHandle};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+/// An operation that mutates the data with a closure.
+///
+/// Wrap the data in [`Applied`] to have it absorb `Apply` operations, so a data structure that
+/// is only ever changed by closures does not need an operation type of its own. The closure
+/// runs once against each copy, so it must make the same change every time.
+///
+/// ```
+/// use splitwrite::{Applied, Apply};
+///
+/// #[derive(Clone, Default)]
+/// struct Config {
+///     timeout: u64,
+/// }
+///
+/// let (mut w, r) = splitwrite::new::<Applied<Config>, Apply<Config>>();
+/// w.apply(|cfg| cfg.timeout = 5);
+/// w.publish();
+/// assert_eq!(r.enter().unwrap().timeout, 5);
+/// ```
+pub struct Apply<T>(Box<dyn Fn(&mut T) + Send>);
+
+/// A `T` that absorbs [`Apply`] operations.
+///
+/// This is opt-in, rather than implemented for every `T: Clone`, so that it never competes
+/// with a type's own [`Absorb`] implementations. It dereferences to the `T` it wraps.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Applied<T>(pub T);
+
+impl<T> Applied<T> {
+    /// Unwrap the data.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Applied<T> {
+    fn from(t: T) -> Self {
+        Applied(t)
+    }
+}
+
+impl<T> Deref for Applied<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Applied<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Applied<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Applied<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Applied)
+    }
+}
+
+impl<T> Apply<T> {
+    /// Wrap `f` so it can be appended to a [`WriteHandle`].
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&mut T) + Send + 'static,
+    {
+        Apply(Box::new(f))
+    }
+}
+
+impl<T> fmt::Debug for Apply<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Apply").field(&"_").finish()
+    }
+}
+
+impl<T> Absorb<Apply<T>> for Applied<T>
+where
+    T: Clone,
+{
+    fn absorb_first(&mut self, operation: &mut Apply<T>, _: &Self) {
+        (operation.0)(&mut self.0)
+    }
+
+    fn absorb_second(&mut self, operation: Apply<T>, _: &Self) {
+        (operation.0)(&mut self.0)
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0)
+    }
+}
+
+impl<T> Preview<Apply<T>> for Applied<T>
+where
+    T: Clone,
+{
+    fn preview(&mut self, operation: &Apply<T>) {
+        (operation.0)(&mut self.0)
+    }
+}
+
+impl<T> WriteHandle<Applied<T>, Apply<T>>
+where
+    T: Clone,
+{
+    /// Append a closure that mutates the data, to be run at the next
+    /// [`publish`](Self::publish).
+    ///
+    /// See [`Apply`].
+    pub fn apply<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&mut T) + Send + 'static,
+    {
+        self.append(Apply::new(f))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Applied, Apply};
+
+    #[test]
+    fn apply_runs_once_per_copy() {
+        let (mut w, r) = crate::new::<Applied<Vec<i32>>, Apply<Vec<i32>>>();
+        w.apply(|v| v.push(1));
+        w.publish();
+        assert_eq!(**r.enter().unwrap(), [1]);
+
+        w.apply(|v| v.push(2)).apply(|v| v.retain(|&x| x != 1));
+        w.publish();
+        assert_eq!(**r.enter().unwrap(), [2]);
+
+        w.publish();
+        assert_eq!(**r.enter().unwrap(), [2]);
+        assert_eq!(**w.take(), [2]);
+    }
+}
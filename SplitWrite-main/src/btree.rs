@@ -0,0 +1,380 @@
+//! A concurrent ordered map backed by a [`BTreeMap`], with range reads.
+//!
+//! Like [`map`](crate::map), keys are cloned into both copies of the map while values are stored
+//! once and aliased between the copies.
+//!
+//! ```
+//! let (mut w, r) = splitwrite::btree::new();
+//!
+//! for ts in 0..10 {
+//!     w.insert(ts, ts * 100);
+//! }
+//! w.publish();
+//!
+//! let map = r.enter().unwrap();
+//! assert_eq!(map.first_key_value(), Some((&0, &0)));
+//! assert_eq!(map.range(3..5).map(|(_, v)| *v).collect::<Vec<_>>(), [300, 400]);
+//! ```
+use crate::aliasing::{Aliased, DoDrop, NoDrop};
+use crate::handles::{read_handle, write_handle};
+use crate::{Absorb, ReadGuard};
+use std::borrow::Borrow;
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::ops::RangeBounds;
+
+/// An ordered map as seen through a [`ReadHandle`].
+///
+/// Every copy of the data has its own B-tree, with its own clone of each key, so each copy keeps
+/// its keys in order by itself. Each value is stored once and shared between all of the trees.
+pub struct SplitBTreeMap<K, V> {
+    data: BTreeMap<K, Aliased<V, NoDrop>>,
+}
+
+impl<K, V> Default for SplitBTreeMap<K, V> {
+    fn default() -> Self {
+        Self {
+            data: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for SplitBTreeMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> SplitBTreeMap<K, V>
+where
+    K: Ord,
+{
+    /// Returns a reference to the value for `key`, if any.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data.get(key).map(|v| &**v)
+    }
+
+    /// Returns true if the map contains a value for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.data.contains_key(key)
+    }
+
+    /// Iterate over the entries whose keys fall within `range`, in ascending key order.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`BTreeMap::range`].
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range {
+            inner: self.data.range(range),
+        }
+    }
+}
+
+impl<K, V> SplitBTreeMap<K, V> {
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.data.iter().next().map(|(k, v)| (k, &**v))
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.data.iter().next_back().map(|(k, v)| (k, &**v))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Iterate over all the entries in the map, in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.data.iter(),
+        }
+    }
+
+    /// Iterate over all the keys in the map, in ascending order.
+    pub fn keys(&self) -> btree_map::Keys<'_, K, Aliased<V, NoDrop>> {
+        self.data.keys()
+    }
+
+    /// Iterate over all the values in the map, in ascending key order.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> {
+        self.data.values().map(|v| &**v)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SplitBTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a [`SplitBTreeMap`].
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    inner: btree_map::Iter<'a, K, Aliased<V, NoDrop>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k, &**v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, v)| (k, &**v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+/// An iterator over a sub-range of the entries of a [`SplitBTreeMap`].
+#[derive(Debug)]
+pub struct Range<'a, K, V> {
+    inner: btree_map::Range<'a, K, Aliased<V, NoDrop>>,
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k, &**v))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Range<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, v)| (k, &**v))
+    }
+}
+
+pub(crate) enum Operation<K, V> {
+    Insert(K, Aliased<V, NoDrop>),
+    Remove(K),
+    SplitOff(K),
+    Clear,
+}
+
+impl<K, V> fmt::Debug for Operation<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Insert(k, v) => f.debug_tuple("Insert").field(k).field(v).finish(),
+            Operation::Remove(k) => f.debug_tuple("Remove").field(k).finish(),
+            Operation::SplitOff(k) => f.debug_tuple("SplitOff").field(k).finish(),
+            Operation::Clear => f.write_str("Clear"),
+        }
+    }
+}
+
+impl<K, V> Absorb<Operation<K, V>> for SplitBTreeMap<K, V>
+where
+    K: Ord + Clone,
+{
+    fn absorb_first(&mut self, operation: &mut Operation<K, V>, _: &Self) {
+        // an inserted value stays owned by the operation until the last copy absorbs it, and a
+        // value replaced, removed, split off or cleared out of this tree is still in the trees
+        // that have yet to see the operation, so nothing is dropped here.
+        match operation {
+            Operation::Insert(key, value) => {
+                self.data.insert(key.clone(), unsafe { value.alias() });
+            }
+            Operation::Remove(key) => {
+                self.data.remove(key);
+            }
+            Operation::SplitOff(key) => {
+                self.data.split_off(key);
+            }
+            Operation::Clear => {
+                self.data.clear();
+            }
+        }
+    }
+
+    fn absorb_second(&mut self, operation: Operation<K, V>, _: &Self) {
+        // safety: every other tree has already absorbed the operation, so a value that an insert
+        // replaces, or that remove, split_off or clear take out, is now in none of them. the
+        // writer has also waited for readers of this tree to leave, so no one can still be
+        // looking at the value when it is dropped here.
+        match operation {
+            Operation::Insert(key, value) => {
+                if let Some(old) = self.data.insert(key, value) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Remove(key) => {
+                if let Some(old) = self.data.remove(&key) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::SplitOff(key) => {
+                for (_, old) in self.data.split_off(&key) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Clear => {
+                for (_, old) in std::mem::take(&mut self.data) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+        }
+    }
+
+    fn drop_op(operation: Operation<K, V>) {
+        // the operation was never absorbed, so it holds the only alias of its value.
+        if let Operation::Insert(_, value) = operation {
+            drop(unsafe { value.change_drop::<DoDrop>() });
+        }
+    }
+
+    fn drop_second(self: Box<Self>) {
+        for (_, value) in self.data {
+            drop(unsafe { value.change_drop::<DoDrop>() });
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        assert_eq!(self.data.len(), 0);
+        self.data.extend(
+            first
+                .data
+                .iter()
+                .map(|(k, v)| (k.clone(), unsafe { v.alias() })),
+        );
+    }
+}
+
+read_handle! {
+    /// A handle that may be used to read from a [`SplitBTreeMap`].
+    ///
+    /// Like [`crate::ReadHandle`], each thread should use its own handle; clone one per thread.
+    pub struct ReadHandle<K, V>(SplitBTreeMap<K, V>);
+}
+
+impl<K, V> ReadHandle<K, V> {
+    /// Take a snapshot of the published map.
+    ///
+    /// Range reads and ordered iteration go through the returned guard. Returns `None` if the
+    /// [`WriteHandle`] has been dropped.
+    pub fn enter(&self) -> Option<ReadGuard<'_, SplitBTreeMap<K, V>>> {
+        self.handle.enter()
+    }
+
+    /// Returns the number of published entries in the map.
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |m| m.len())
+    }
+
+    /// Returns true if the published map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.enter().is_none_or(|m| m.is_empty())
+    }
+}
+
+impl<K, V> ReadHandle<K, V>
+where
+    K: Ord,
+{
+    /// Returns a guarded reference to the published value for `key`, if any.
+    pub fn get<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        ReadGuard::try_map(self.enter()?, |m| m.get(key))
+    }
+
+    /// Returns true if the published map contains a value for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.enter().is_some_and(|m| m.contains_key(key))
+    }
+}
+
+write_handle! {
+    /// A handle that may be used to modify a [`SplitBTreeMap`].
+    ///
+    /// Modifications are only visible to readers after [`publish`](Self::publish). The handle
+    /// dereferences to a [`ReadHandle`] that sees the published map.
+    pub struct WriteHandle<K, V>(SplitBTreeMap<K, V>, Operation<K, V>)
+    where
+        K: Ord + Clone,
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Ord + Clone,
+{
+    /// Insert `value` for `key`, replacing any existing value.
+    pub fn insert(&mut self, key: K, value: V) -> &mut Self {
+        self.handle
+            .append(Operation::Insert(key, Aliased::from(value)));
+        self
+    }
+
+    /// Remove the value for `key`, if any.
+    pub fn remove(&mut self, key: K) -> &mut Self {
+        self.handle.append(Operation::Remove(key));
+        self
+    }
+
+    /// Remove every entry whose key is greater than or equal to `at`.
+    ///
+    /// This is [`BTreeMap::split_off`], except that the split-off entries are dropped.
+    pub fn split_off(&mut self, at: K) -> &mut Self {
+        self.handle.append(Operation::SplitOff(at));
+        self
+    }
+
+    /// Remove all entries from the map.
+    pub fn clear(&mut self) -> &mut Self {
+        self.handle.append(Operation::Clear);
+        self
+    }
+}
+
+/// Create an empty ordered map, returning its write handle and a read handle.
+pub fn new<K, V>() -> (WriteHandle<K, V>, ReadHandle<K, V>)
+where
+    K: Ord + Clone,
+{
+    let (w, r) = crate::new();
+    WriteHandle::from_handles(w, r)
+}
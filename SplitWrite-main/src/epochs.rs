@@ -0,0 +1,268 @@
+//! The registry of reader epoch counters.
+//!
+//! Every [`ReadHandle`](crate::ReadHandle) owns a slot in the registry, and the writer scans
+//! all slots to find readers that may still be in the copy it is about to modify. Registering
+//! and releasing a slot never blocks, so creating a read handle cannot get stuck behind a
+//! publish that is waiting for readers.
+//!
+//! Slots live in segments that double in size, so a slot never moves once allocated and can be
+//! handed out by reference. Released slots go on a free list for reuse. A slot's counter is not
+//! reset when it is reused: it is even whenever the slot is released, and since it only ever
+//! grows, the writer cannot mistake a new reader for the old one it was waiting for.
+
+use crate::sync::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::{fmt, ptr};
+
+/// The first segment has `1 << FIRST_SHIFT` slots.
+const FIRST_SHIFT: u32 = 4;
+
+/// With this many segments, slot indices fit in the 32 bits the free list has room for.
+const SEGMENTS: usize = 28;
+
+/// The low half of the free list head is the index of the first free slot plus one, and the
+/// high half is bumped on every change so that a stale compare-and-swap fails.
+const INDEX_MASK: u64 = u32::MAX as u64;
+const TAG: u64 = 1 << 32;
+
+/// An epoch counter, padded to avoid false sharing between readers.
+#[repr(align(128))]
+struct Slot {
+    epoch: AtomicUsize,
+    /// The next slot on the free list, plus one, while this slot is on it.
+    next_free: AtomicUsize,
+}
+
+pub(crate) struct Registry {
+    segments: [AtomicPtr<Slot>; SEGMENTS],
+    /// How many slots have ever been handed out.
+    capacity: AtomicUsize,
+    /// How many slots are currently in use.
+    registered: AtomicUsize,
+    free: AtomicU64,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            segments: std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            capacity: AtomicUsize::new(0),
+            registered: AtomicUsize::new(0),
+            free: AtomicU64::new(0),
+        }
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("capacity", &self.capacity())
+            .field("registered", &self.len())
+            .finish()
+    }
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        for (segment, slots) in self.segments.iter().enumerate() {
+            let slots = slots.load(Ordering::Relaxed);
+            if !slots.is_null() {
+                drop(unsafe {
+                    Box::from_raw(ptr::slice_from_raw_parts_mut(slots, segment_len(segment)))
+                });
+            }
+        }
+    }
+}
+
+fn segment_len(segment: usize) -> usize {
+    1 << (FIRST_SHIFT as usize + segment)
+}
+
+/// The segment and offset within it of the `index`th slot.
+fn locate(index: usize) -> (usize, usize) {
+    let i = index + (1 << FIRST_SHIFT);
+    let segment = (usize::BITS - 1 - i.leading_zeros() - FIRST_SHIFT) as usize;
+    (segment, i - segment_len(segment))
+}
+
+impl Registry {
+    /// Allocate a slot for a new reader, and return its index.
+    ///
+    /// The slot's epoch is even.
+    pub(crate) fn register(&self) -> usize {
+        let index = self.pop_free().unwrap_or_else(|| {
+            // this is ordered against the fence the writer issues before it reads the capacity,
+            // just like the reader's SeqCst epoch increment in `enter`: either the writer sees
+            // the new slot, or the reader that registered it only ever enters the copy that the
+            // writer just published.
+            let index = self.capacity.fetch_add(1, Ordering::SeqCst);
+            let (segment, _) = locate(index);
+            assert!(segment < SEGMENTS, "too many read handles");
+            self.install(segment);
+            index
+        });
+        self.registered.fetch_add(1, Ordering::Relaxed);
+        index
+    }
+
+    /// Return a slot to the registry. Its epoch must be even.
+    pub(crate) fn release(&self, index: usize) {
+        debug_assert!(self.epoch(index).load(Ordering::Relaxed).is_multiple_of(2));
+        self.registered.fetch_sub(1, Ordering::Relaxed);
+
+        let slot = self.slot(index).expect("released slots were registered");
+        let mut head = self.free.load(Ordering::Relaxed);
+        loop {
+            slot.next_free
+                .store((head & INDEX_MASK) as usize, Ordering::Relaxed);
+            let new = ((head & !INDEX_MASK).wrapping_add(TAG)) | (index as u64 + 1);
+            match self
+                .free
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    fn pop_free(&self) -> Option<usize> {
+        let mut head = self.free.load(Ordering::Acquire);
+        loop {
+            let top = (head & INDEX_MASK) as usize;
+            if top == 0 {
+                return None;
+            }
+            // the slot may have been popped and pushed again since we read the head, in which
+            // case `next_free` is garbage, but then the tag has moved on and the swap fails.
+            let slot = self.slot(top - 1).expect("free slots were registered");
+            let next = slot.next_free.load(Ordering::Relaxed) as u64;
+            let new = ((head & !INDEX_MASK).wrapping_add(TAG)) | next;
+            match self
+                .free
+                .compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return Some(top - 1),
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Make sure `segment` is allocated.
+    fn install(&self, segment: usize) {
+        if !self.segments[segment].load(Ordering::Acquire).is_null() {
+            return;
+        }
+
+        let slots: Box<[Slot]> = (0..segment_len(segment))
+            .map(|_| Slot {
+                epoch: AtomicUsize::new(0),
+                next_free: AtomicUsize::new(0),
+            })
+            .collect();
+        let slots = Box::into_raw(slots) as *mut Slot;
+        if self.segments[segment]
+            .compare_exchange(ptr::null_mut(), slots, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // another reader got there first.
+            drop(unsafe {
+                Box::from_raw(ptr::slice_from_raw_parts_mut(slots, segment_len(segment)))
+            });
+        }
+    }
+
+    fn slot(&self, index: usize) -> Option<&Slot> {
+        let (segment, offset) = locate(index);
+        let slots = self.segments[segment].load(Ordering::Acquire);
+        if slots.is_null() {
+            return None;
+        }
+        // Safety: segments are never freed before the registry is, and `offset` is in bounds.
+        Some(unsafe { &*slots.add(offset) })
+    }
+
+    /// The epoch counter of a registered slot.
+    pub(crate) fn epoch(&self, index: usize) -> &AtomicUsize {
+        &self.slot(index).expect("slot was registered").epoch
+    }
+
+    /// The number of slots that have ever been handed out, which bounds every slot index.
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Acquire)
+    }
+
+    /// The number of slots currently in use.
+    pub(crate) fn len(&self) -> usize {
+        self.registered.load(Ordering::Relaxed)
+    }
+
+    /// Iterate over the epoch counters of all slots from `start` onwards, in index order.
+    ///
+    /// This includes slots that are on the free list, whose epochs are even. Slots that are
+    /// registered concurrently with the iteration may or may not be included.
+    pub(crate) fn iter_from(&self, start: usize) -> impl Iterator<Item = (usize, &AtomicUsize)> {
+        (start..self.capacity()).filter_map(move |index| Some((index, &self.slot(index)?.epoch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_slots() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(15), (0, 15));
+        assert_eq!(locate(16), (1, 0));
+        assert_eq!(locate(47), (1, 31));
+        assert_eq!(locate(48), (2, 0));
+    }
+
+    #[test]
+    fn reuse_keeps_counting() {
+        let registry = Registry::default();
+        let slots: Vec<_> = (0..40).map(|_| registry.register()).collect();
+        assert_eq!(slots, (0..40).collect::<Vec<_>>());
+        assert_eq!(registry.len(), 40);
+        assert_eq!(registry.iter_from(38).count(), 2);
+
+        registry.epoch(17).store(6, Ordering::Relaxed);
+        registry.release(3);
+        registry.release(17);
+        assert_eq!(registry.len(), 38);
+        assert_eq!(registry.register(), 17);
+        assert_eq!(registry.epoch(17).load(Ordering::Relaxed), 6);
+        assert_eq!(registry.register(), 3);
+        assert_eq!(registry.register(), 40);
+        assert_eq!(registry.capacity(), 41);
+    }
+
+    #[test]
+    fn concurrent_registration() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let registry = Arc::new(Registry::default());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let registry = Arc::clone(&registry);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        let index = registry.register();
+                        // no other thread may be using the slot while we hold it.
+                        let epoch = registry.epoch(index);
+                        assert!(epoch.fetch_add(1, Ordering::SeqCst).is_multiple_of(2));
+                        assert!(!epoch.fetch_add(1, Ordering::SeqCst).is_multiple_of(2));
+                        registry.release(index);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(registry.len(), 0);
+        assert!(registry.capacity() <= 8);
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+#[cfg(feature = "async")]
+use std::task::Waker;
+
+/// Counts publishes, and lets readers wait for the count to go up.
+///
+/// It also records which publish each of the copies was last published by, keyed by the address
+/// of the copy.
+#[derive(Debug, Default)]
+pub(crate) struct Generation {
+    current: AtomicU64,
+    copies: OnceLock<Box<[usize]>>,
+    versions: OnceLock<Box<[AtomicU64]>>,
+    waiters: Mutex<Waiters>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Waiters {
+    closed: bool,
+    #[cfg(feature = "async")]
+    wakers: Vec<Waker>,
+}
+
+impl Generation {
+    pub(crate) fn current(&self) -> u64 {
+        self.current.load(Ordering::Acquire)
+    }
+
+    /// Remember the addresses of the copies, all of which start out at version 0.
+    pub(crate) fn register(&self, copies: Box<[usize]>) {
+        let versions = copies.iter().map(|_| AtomicU64::new(0)).collect();
+        assert!(
+            self.copies.set(copies).is_ok(),
+            "copies are registered once"
+        );
+        assert!(self.versions.set(versions).is_ok());
+    }
+
+    fn slot(&self, copy: usize) -> &AtomicU64 {
+        let copies = self.copies.get().expect("copies were registered");
+        let slot = copies
+            .iter()
+            .position(|&c| c == copy)
+            .expect("not one of the registered copies");
+        &self.versions.get().expect("copies were registered")[slot]
+    }
+
+    /// Stamp `copy` with the version it is about to be published as.
+    ///
+    /// Must happen before the copy is made visible to readers.
+    pub(crate) fn stamp(&self, copy: usize) {
+        let version = self.current() + 1;
+        self.slot(copy).store(version, Ordering::Release);
+    }
+
+    /// The version of `copy`, which the caller must be holding a guard into.
+    pub(crate) fn version_of(&self, copy: usize) -> u64 {
+        self.slot(copy).load(Ordering::Acquire)
+    }
+
+    /// Record a publish, and wake everyone waiting for one.
+    pub(crate) fn bump(&self) {
+        let mut waiters = self.waiters.lock().unwrap();
+        self.current.fetch_add(1, Ordering::AcqRel);
+        self.wake(&mut waiters);
+    }
+
+    /// Record that there will be no more publishes.
+    pub(crate) fn close(&self) {
+        let mut waiters = self.waiters.lock().unwrap();
+        waiters.closed = true;
+        self.wake(&mut waiters);
+    }
+
+    #[cfg_attr(not(feature = "async"), allow(unused_variables))]
+    fn wake(&self, waiters: &mut Waiters) {
+        #[cfg(feature = "async")]
+        for waker in waiters.wakers.drain(..) {
+            waker.wake();
+        }
+        self.changed.notify_all();
+    }
+
+    /// Block until the generation is at least `g`, and return it.
+    ///
+    /// Returns `None` if the writer goes away first.
+    pub(crate) fn wait_for(&self, g: u64) -> Option<u64> {
+        let mut waiters = self.waiters.lock().unwrap();
+        loop {
+            let current = self.current();
+            if current >= g {
+                return Some(current);
+            }
+            if waiters.closed {
+                return None;
+            }
+            waiters = self.changed.wait(waiters).unwrap();
+        }
+    }
+
+    /// Like `wait_for`, but registers `waker` instead of blocking.
+    #[cfg(feature = "async")]
+    pub(crate) fn poll_for(&self, g: u64, waker: &Waker) -> std::task::Poll<Option<u64>> {
+        use std::task::Poll;
+
+        let mut waiters = self.waiters.lock().unwrap();
+        let current = self.current();
+        if current >= g {
+            return Poll::Ready(Some(current));
+        }
+        if waiters.closed {
+            return Poll::Ready(None);
+        }
+        if !waiters.wakers.iter().any(|w| w.will_wake(waker)) {
+            waiters.wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+}
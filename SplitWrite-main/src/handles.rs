@@ -0,0 +1,148 @@
+//! Handle plumbing shared by [`map`](crate::map), [`multimap`](crate::multimap),
+//! [`vec`](crate::vec) and [`btree`](crate::btree).
+//!
+//! Each of those wraps a [`crate::ReadHandle`] and a [`crate::WriteHandle`] around its own data
+//! type, and only adds the reads and operations particular to that type. The macros here declare
+//! the wrappers themselves, and everything about them that does not depend on the data type.
+
+/// Declare a `ReadHandle` around a [`crate::ReadHandle`] to `$data`, with [`Clone`],
+/// [`Debug`](std::fmt::Debug) and `was_dropped`.
+///
+/// Reads, including `enter`, are left to the caller, since what they hand out differs.
+macro_rules! read_handle {
+    (
+        $(#[$attr:meta])*
+        pub struct ReadHandle<$($g:ident),+>($data:ty);
+    ) => {
+        $(#[$attr])*
+        pub struct ReadHandle<$($g),+> {
+            handle: $crate::ReadHandle<$data>,
+        }
+
+        impl<$($g),+> Clone for ReadHandle<$($g),+> {
+            fn clone(&self) -> Self {
+                Self {
+                    handle: self.handle.clone(),
+                }
+            }
+        }
+
+        impl<$($g),+> std::fmt::Debug for ReadHandle<$($g),+> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct("ReadHandle")
+                    .field("handle", &self.handle)
+                    .finish()
+            }
+        }
+
+        impl<$($g),+> ReadHandle<$($g),+> {
+            /// Returns true if the [`WriteHandle`] has been dropped.
+            pub fn was_dropped(&self) -> bool {
+                self.handle.was_dropped()
+            }
+        }
+    };
+}
+pub(crate) use read_handle;
+
+/// Declare a `WriteHandle` around a [`crate::WriteHandle`] to `$data` that takes `$op`s, next to a
+/// `ReadHandle` declared with [`read_handle`].
+///
+/// The handle gets [`Debug`](std::fmt::Debug), publishing and rolling back, dereferences to its
+/// `ReadHandle`, and is built with `WriteHandle::from_handles`. Any extra fields are set to their
+/// initial value there, and `on_rollback` runs after a rollback that discarded operations, to
+/// bring them back in line with the published data.
+macro_rules! write_handle {
+    (
+        $(#[$attr:meta])*
+        pub struct WriteHandle<$($g:ident),+>($data:ty, $op:ty)
+        $({
+            $($field:ident: $fty:ty = $init:expr),* $(,)?
+        })?
+        $(on_rollback(|$this:ident| $rolled_back:block))?
+        $(where $($bound:tt)+)?
+    ) => {
+        $(#[$attr])*
+        pub struct WriteHandle<$($g),+>
+        $(where $($bound)+)?
+        {
+            handle: $crate::WriteHandle<$data, $op>,
+            r_handle: ReadHandle<$($g),+>,
+            $($($field: $fty,)*)?
+        }
+
+        impl<$($g),+> std::fmt::Debug for WriteHandle<$($g),+>
+        where
+            $($g: std::fmt::Debug,)+
+            $($($bound)+)?
+        {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct("WriteHandle")
+                    .field("handle", &self.handle)
+                    $($(.field(stringify!($field), &self.$field))*)?
+                    .finish()
+            }
+        }
+
+        impl<$($g),+> WriteHandle<$($g),+>
+        $(where $($bound)+)?
+        {
+            fn from_handles(
+                handle: $crate::WriteHandle<$data, $op>,
+                r_handle: $crate::ReadHandle<$data>,
+            ) -> (Self, ReadHandle<$($g),+>) {
+                let r_handle = ReadHandle { handle: r_handle };
+                let w = Self {
+                    handle,
+                    r_handle: r_handle.clone(),
+                    $($($field: $init,)*)?
+                };
+                (w, r_handle)
+            }
+
+            /// Publish all changes since the last publish to readers.
+            ///
+            /// See [`crate::WriteHandle::publish`].
+            pub fn publish(&mut self) -> &mut Self {
+                self.handle.publish();
+                self
+            }
+
+            /// Publish, but only if there are changes waiting to be published.
+            pub fn flush(&mut self) {
+                self.handle.flush();
+            }
+
+            /// Returns true if there are changes that have not yet been published.
+            pub fn has_pending_operations(&self) -> bool {
+                self.handle.has_pending_operations()
+            }
+
+            /// Discard all changes since the last publish.
+            ///
+            /// See [`crate::WriteHandle::rollback`].
+            pub fn rollback(&mut self) -> &mut Self {
+                // operations appended before the first publish are never pending, and are not
+                // rolled back, so only run the hook when there was something to discard.
+                if self.handle.has_pending_operations() {
+                    self.handle.rollback();
+                    $({
+                        let $this = &mut *self;
+                        $rolled_back
+                    })?
+                }
+                self
+            }
+        }
+
+        impl<$($g),+> std::ops::Deref for WriteHandle<$($g),+>
+        $(where $($bound)+)?
+        {
+            type Target = ReadHandle<$($g),+>;
+            fn deref(&self) -> &Self::Target {
+                &self.r_handle
+            }
+        }
+    };
+}
+pub(crate) use write_handle;
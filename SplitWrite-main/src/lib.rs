@@ -1,4 +1,23 @@
-
+//! A concurrency primitive for read-heavy workloads.
+//!
+//! `splitwrite` keeps two copies of a data structure. Readers access one copy without taking
+//! any locks, while the single writer mutates the other. When the writer [publishes], the two
+//! copies are swapped, and the writer then replays the same operations onto the copy readers
+//! just left. Operations are described by a user-defined type `O`, and the data structure `T`
+//! tells the crate how to apply them by implementing [`Absorb<O>`](Absorb).
+//!
+//! Publishing waits for readers to leave the copy the writer is about to modify. Writers that
+//! cannot afford to block can use [`WriteHandle::try_publish`], and with the `async` feature,
+//! writers running inside an async runtime can use `WriteHandle::publish_async`, which yields to
+//! the runtime instead of spinning. Where readers hold on to their guards for a long time,
+//! [`new_with_copies`] keeps more than two copies, so that the writer only waits for readers of
+//! the oldest one. With the `serde` feature, `ReadHandle::snapshot_to` and `restore_from` save
+//! and load the published data, and with the `wal` feature, the `wal` module logs every operation
+//! to disk so the data survives a restart. The `replication` feature adds the `replication`
+//! module, for read replicas in other processes, and the `shm` feature adds the `shm` module,
+//! for readers in other processes on the same host that read straight from shared memory.
+//!
+//! [publishes]: WriteHandle::publish
 #![warn(
     missing_docs,
     rust_2018_idioms,
@@ -7,37 +26,134 @@
 )]
 #![allow(clippy::type_complexity)]
 
+mod epochs;
+mod generation;
 mod sync;
+mod wakeup;
 
-use crate::sync::{Arc, AtomicUsize, Mutex};
+use crate::sync::Arc;
 
-type Epochs = Arc<Mutex<slab::Slab<Arc<AtomicUsize>>>>;
+type Epochs = Arc<epochs::Registry>;
 
 mod write;
+pub use crate::write::Savepoint;
+pub use crate::write::SharedWriteHandle;
 pub use crate::write::Taken;
+pub use crate::write::Transaction;
+pub use crate::write::WaitStrategy;
+pub use crate::write::WouldBlock;
 pub use crate::write::WriteHandle;
 
 mod read;
-pub use crate::read::{ReadGuard, ReadHandle, ReadHandleFactory};
+pub use crate::read::{ReadGuard, ReadHandle, ReadHandleFactory, SharedReadHandle};
+
+mod apply;
+pub use crate::apply::{Applied, Apply};
+
+mod publisher;
+pub use crate::publisher::{AutoPublisher, PublishPolicy, Submitter};
+
+#[cfg(feature = "serde")]
+mod snapshot;
+
+pub mod sharded;
 
 pub mod aliasing;
 
+mod handles;
+
+pub mod map;
+
+pub mod multimap;
+
+pub mod vec;
+
+pub mod btree;
+
+#[cfg(feature = "wal")]
+pub mod wal;
+
+#[cfg(feature = "replication")]
+pub mod replication;
 
+#[cfg(feature = "shm")]
+pub mod shm;
+
+/// Types that can incorporate operations of type `O`.
+///
+/// Every operation is applied to every copy of the data, of which there are two unless the
+/// handles were made with [`new_with_copies`]: with [`absorb_first`] to each copy that sees it
+/// before the last, and once with [`absorb_second`] to the last copy. All must leave the data in
+/// the same state, or the copies will diverge.
+///
+/// With the `derive` feature, `#[derive(Absorb)]` on an operation enum generates this impl from
+/// one mutation method per variant.
+///
+/// [`absorb_first`]: Absorb::absorb_first
+/// [`absorb_second`]: Absorb::absorb_second
 pub trait Absorb<O> {
-   
+    /// Apply `operation` to a copy other than the last one to see it.
+    ///
+    /// `other` is the copy readers currently see, which already reflects `operation`.
     fn absorb_first(&mut self, operation: &mut O, other: &Self);
 
+    /// Apply `operation` to the last copy to see it, after which the operation is dropped.
+    ///
+    /// Defaults to calling [`absorb_first`](Absorb::absorb_first).
     fn absorb_second(&mut self, mut operation: O, other: &Self) {
         Self::absorb_first(self, &mut operation, other)
     }
 
+    /// Drop an operation that was never absorbed into any copy, because it was rolled back.
+    ///
+    /// Defaults to dropping the operation normally. Operations that carry values to be aliased
+    /// into the copies should override this to drop those values.
+    ///
+    /// See [`WriteHandle::rollback`].
+    fn drop_op(operation: O)
+    where
+        Self: Sized,
+    {
+        drop(operation);
+    }
+
+    /// Drop each copy but the last when the [`WriteHandle`] goes away.
     #[allow(clippy::boxed_local)]
     fn drop_first(self: Box<Self>) {}
+
+    /// Drop the last copy when the [`WriteHandle`] goes away.
     #[allow(clippy::boxed_local)]
     fn drop_second(self: Box<Self>) {}
 
+    /// Bring a freshly constructed copy up to date with `first`.
+    ///
+    /// Called once for each copy that did not see the operations that were applied before the
+    /// first publish, when that copy is first written to after it. `first` is the copy that saw
+    /// them, as it was at the first publish; the operations appended since are then absorbed as
+    /// usual.
     fn sync_with(&mut self, first: &Self);
 }
+
+/// Types that a [`Transaction`] can show with pending operations of type `O` applied, through
+/// [`Transaction::current`].
+///
+/// The transaction applies pending operations to a clone of the published data that no reader
+/// ever sees. That clone is not one of the copies of the data, so previewing an operation is
+/// kept apart from [`Absorb`], and every operation is still absorbed exactly once into each copy.
+pub trait Preview<O>: Absorb<O> + Clone {
+    /// Apply `operation` to a clone of the data that readers never see.
+    ///
+    /// This must leave the data as [`Absorb::absorb_first`] would, without changing the
+    /// operation.
+    fn preview(&mut self, operation: &O);
+}
+
+#[cfg(feature = "derive")]
+pub use splitwrite_derive::Absorb;
+
+/// Construct a new write and read handle pair from an initial value.
+///
+/// The value is cloned once to produce the second copy.
 pub fn new_from_empty<T, O>(t: T) -> (WriteHandle<T, O>, ReadHandle<T>)
 where
     T: Absorb<O> + Clone,
@@ -45,10 +161,26 @@ where
     let epochs = Default::default();
 
     let r = ReadHandle::new(t.clone(), Arc::clone(&epochs));
-    let w = WriteHandle::new(t, epochs, r.clone());
+    let w = WriteHandle::new(t, Vec::new(), epochs, r.clone());
     (w, r)
 }
 
+/// Construct a new write and read handle pair from a snapshot written by
+/// [`ReadHandle::snapshot_to`].
+///
+/// Like [`new_from_empty`], the restored value is cloned once to produce the second copy.
+/// Reads exactly the bytes of the snapshot from `reader`, which should be buffered.
+#[cfg(feature = "serde")]
+pub fn restore_from<T, O, R>(reader: R) -> std::io::Result<(WriteHandle<T, O>, ReadHandle<T>)>
+where
+    T: Absorb<O> + Clone + serde::de::DeserializeOwned,
+    R: std::io::Read,
+{
+    let (t, _) = snapshot::read(reader)?;
+    Ok(new_from_empty(t))
+}
+
+/// Construct a new write and read handle pair from the [`Default`] of `T`.
 pub fn new<T, O>() -> (WriteHandle<T, O>, ReadHandle<T>)
 where
     T: Absorb<O> + Default,
@@ -56,6 +188,51 @@ where
     let epochs = Default::default();
 
     let r = ReadHandle::new(T::default(), Arc::clone(&epochs));
-    let w = WriteHandle::new(T::default(), epochs, r.clone());
+    let w = WriteHandle::new(T::default(), Vec::new(), epochs, r.clone());
+    (w, r)
+}
+
+/// Like [`new_from_empty`], but with `copies` copies of the data rather than two.
+///
+/// Before applying operations to a copy, the writer waits for readers to leave it. With two
+/// copies, that is the copy readers saw until the previous publish, so a single long-lived
+/// guard holds up every publish. With more copies, the writer moves on to the copy that was
+/// published the longest ago, and only has to wait for guards taken out before it was replaced,
+/// `copies - 1` publishes back. Each additional copy costs the memory of another `T`, and
+/// every operation is applied once per copy.
+///
+/// # Panics
+///
+/// If `copies` is less than two.
+pub fn new_from_empty_with_copies<T, O>(t: T, copies: usize) -> (WriteHandle<T, O>, ReadHandle<T>)
+where
+    T: Absorb<O> + Clone,
+{
+    assert!(copies >= 2, "left-right needs at least two copies");
+    let epochs = Default::default();
+
+    let spares = (2..copies).map(|_| t.clone()).collect();
+    let r = ReadHandle::new(t.clone(), Arc::clone(&epochs));
+    let w = WriteHandle::new(t, spares, epochs, r.clone());
+    (w, r)
+}
+
+/// Like [`new`], but with `copies` copies of the data rather than two.
+///
+/// See [`new_from_empty_with_copies`].
+///
+/// # Panics
+///
+/// If `copies` is less than two.
+pub fn new_with_copies<T, O>(copies: usize) -> (WriteHandle<T, O>, ReadHandle<T>)
+where
+    T: Absorb<O> + Default,
+{
+    assert!(copies >= 2, "left-right needs at least two copies");
+    let epochs = Default::default();
+
+    let spares = (2..copies).map(|_| T::default()).collect();
+    let r = ReadHandle::new(T::default(), Arc::clone(&epochs));
+    let w = WriteHandle::new(T::default(), spares, epochs, r.clone());
     (w, r)
 }
@@ -0,0 +1,331 @@
+//! A concurrent hash map built on [`WriteHandle`](crate::WriteHandle) and
+//! [`ReadHandle`](crate::ReadHandle).
+//!
+//! Keys are cloned into both copies of the map, but every value is stored only once and
+//! aliased between the copies with [`Aliased`], so values do not need to implement [`Clone`].
+//!
+//! ```
+//! let (mut w, r) = splitwrite::map::new();
+//!
+//! w.insert("x", 1);
+//! assert_eq!(r.get("x").map(|v| *v), None);
+//!
+//! w.publish();
+//! assert_eq!(r.get("x").map(|v| *v), Some(1));
+//! ```
+use crate::aliasing::{Aliased, DoDrop, NoDrop};
+use crate::handles::{read_handle, write_handle};
+use crate::{Absorb, ReadGuard};
+use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+/// A hash map as seen through a [`ReadHandle`].
+///
+/// Every copy of the data has its own table, with its own clone of each key, but each value is
+/// stored once and shared between all of the tables.
+pub struct SplitMap<K, V> {
+    data: HashMap<K, Aliased<V, NoDrop>>,
+}
+
+impl<K, V> Default for SplitMap<K, V> {
+    fn default() -> Self {
+        Self {
+            data: HashMap::new(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for SplitMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> SplitMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns a reference to the value for `key`, if any.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.data.get(key).map(|v| &**v)
+    }
+
+    /// Returns true if the map contains a value for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.data.contains_key(key)
+    }
+}
+
+impl<K, V> SplitMap<K, V> {
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Iterate over all the entries in the map, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.data.iter(),
+        }
+    }
+
+    /// Iterate over all the keys in the map, in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.data.keys()
+    }
+
+    /// Iterate over all the values in the map, in arbitrary order.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.data.values().map(|v| &**v)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SplitMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a [`SplitMap`].
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    inner: hash_map::Iter<'a, K, Aliased<V, NoDrop>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k, &**v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+pub(crate) enum Operation<K, V> {
+    Insert(K, Aliased<V, NoDrop>),
+    Remove(K),
+    Retain(Box<dyn FnMut(&K, &V) -> bool + Send>),
+    Clear,
+}
+
+impl<K, V> fmt::Debug for Operation<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Insert(k, v) => f.debug_tuple("Insert").field(k).field(v).finish(),
+            Operation::Remove(k) => f.debug_tuple("Remove").field(k).finish(),
+            Operation::Retain(_) => f.debug_tuple("Retain").field(&"_").finish(),
+            Operation::Clear => f.write_str("Clear"),
+        }
+    }
+}
+
+impl<K, V> Absorb<Operation<K, V>> for SplitMap<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    fn absorb_first(&mut self, operation: &mut Operation<K, V>, _: &Self) {
+        // an inserted value stays owned by the operation until the last copy absorbs it, and a
+        // value replaced, removed or cleared out of this table is still in the tables that have
+        // yet to see the operation, so nothing is dropped here.
+        match operation {
+            Operation::Insert(key, value) => {
+                self.data.insert(key.clone(), unsafe { value.alias() });
+            }
+            Operation::Remove(key) => {
+                self.data.remove(key);
+            }
+            Operation::Retain(predicate) => {
+                self.data.retain(|k, v| predicate(k, v));
+            }
+            Operation::Clear => {
+                self.data.clear();
+            }
+        }
+    }
+
+    fn absorb_second(&mut self, operation: Operation<K, V>, _: &Self) {
+        // safety: every other table has already absorbed the operation, so a value that an
+        // insert replaces, or that remove, retain or clear take out, is now in none of them. the
+        // writer has also waited for readers of this table to leave, so no one can still be
+        // looking at the value when it is dropped here.
+        match operation {
+            Operation::Insert(key, value) => {
+                if let Some(old) = self.data.insert(key, value) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Remove(key) => {
+                if let Some(old) = self.data.remove(&key) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Retain(mut predicate) => {
+                for (_, old) in self.data.extract_if(|k, v| !predicate(k, v)) {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Clear => {
+                for (_, old) in self.data.drain() {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+        }
+    }
+
+    fn drop_op(operation: Operation<K, V>) {
+        // the operation was never absorbed, so it holds the only alias of its value.
+        if let Operation::Insert(_, value) = operation {
+            drop(unsafe { value.change_drop::<DoDrop>() });
+        }
+    }
+
+    fn drop_second(self: Box<Self>) {
+        let mut this = self;
+        for (_, value) in this.data.drain() {
+            drop(unsafe { value.change_drop::<DoDrop>() });
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        assert_eq!(self.data.len(), 0);
+        self.data.extend(
+            first
+                .data
+                .iter()
+                .map(|(k, v)| (k.clone(), unsafe { v.alias() })),
+        );
+    }
+}
+
+read_handle! {
+    /// A handle that may be used to read from a [`SplitMap`].
+    ///
+    /// Like [`crate::ReadHandle`], each thread should use its own handle; clone one per thread.
+    pub struct ReadHandle<K, V>(SplitMap<K, V>);
+}
+
+impl<K, V> ReadHandle<K, V> {
+    /// Take a snapshot of the published map.
+    ///
+    /// Returns `None` if the [`WriteHandle`] has been dropped.
+    pub fn enter(&self) -> Option<ReadGuard<'_, SplitMap<K, V>>> {
+        self.handle.enter()
+    }
+
+    /// Returns the number of published entries in the map.
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |m| m.len())
+    }
+
+    /// Returns true if the published map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.enter().is_none_or(|m| m.is_empty())
+    }
+}
+
+impl<K, V> ReadHandle<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns a guarded reference to the published value for `key`, if any.
+    pub fn get<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        ReadGuard::try_map(self.enter()?, |m| m.get(key))
+    }
+
+    /// Returns true if the published map contains a value for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.enter().is_some_and(|m| m.contains_key(key))
+    }
+}
+
+write_handle! {
+    /// A handle that may be used to modify a [`SplitMap`].
+    ///
+    /// Modifications are only visible to readers after [`publish`](Self::publish). The handle
+    /// dereferences to a [`ReadHandle`] that sees the published map.
+    pub struct WriteHandle<K, V>(SplitMap<K, V>, Operation<K, V>)
+    where
+        K: Eq + Hash + Clone,
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Insert `value` for `key`, replacing any existing value.
+    pub fn insert(&mut self, key: K, value: V) -> &mut Self {
+        self.handle
+            .append(Operation::Insert(key, Aliased::from(value)));
+        self
+    }
+
+    /// Remove the value for `key`, if any.
+    pub fn remove(&mut self, key: K) -> &mut Self {
+        self.handle.append(Operation::Remove(key));
+        self
+    }
+
+    /// Retain only the entries for which `predicate` returns true.
+    ///
+    /// The predicate is run once against each copy of the map, and must give the same answer
+    /// for an entry both times or the copies will diverge.
+    pub fn retain<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: FnMut(&K, &V) -> bool + Send + 'static,
+    {
+        self.handle.append(Operation::Retain(Box::new(predicate)));
+        self
+    }
+
+    /// Remove all entries from the map.
+    pub fn clear(&mut self) -> &mut Self {
+        self.handle.append(Operation::Clear);
+        self
+    }
+}
+
+/// Create an empty map, returning its write handle and a read handle.
+pub fn new<K, V>() -> (WriteHandle<K, V>, ReadHandle<K, V>)
+where
+    K: Eq + Hash + Clone,
+{
+    let (w, r) = crate::new();
+    WriteHandle::from_handles(w, r)
+}
@@ -0,0 +1,348 @@
+//! A concurrent multi-value map, where every key maps to a bag of values.
+//!
+//! Like [`map`](crate::map), keys are cloned into both copies of the map while values are stored
+//! once and aliased between the copies. Since values are looked up by equality when they are
+//! removed, they must implement [`Eq`] and [`Hash`].
+//!
+//! ```
+//! let (mut w, r) = splitwrite::multimap::new();
+//!
+//! w.insert("x", 1).insert("x", 2);
+//! w.publish();
+//! assert_eq!(r.get("x").map(|vs| vs.len()), Some(2));
+//!
+//! w.remove_value("x", 1);
+//! w.publish();
+//! assert_eq!(r.get_one("x").map(|v| *v), Some(2));
+//! ```
+use crate::aliasing::{Aliased, DoDrop, NoDrop};
+use crate::handles::{read_handle, write_handle};
+use crate::{Absorb, ReadGuard};
+use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+mod values;
+pub use values::{Values, ValuesIter};
+
+/// A hash map from keys to bags of values, as seen through a [`ReadHandle`].
+///
+/// Every copy of the data has its own table and its own bags, with its own clone of each key,
+/// but each value in a bag is stored once and shared between the matching bags of every copy.
+pub struct SplitMultiMap<K, V> {
+    data: HashMap<K, Values<V>>,
+}
+
+impl<K, V> Default for SplitMultiMap<K, V> {
+    fn default() -> Self {
+        Self {
+            data: HashMap::new(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for SplitMultiMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> SplitMultiMap<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns the bag of values for `key`, if the key is present.
+    ///
+    /// A key may be present with an empty bag after [`WriteHandle::empty`].
+    pub fn get<Q>(&self, key: &Q) -> Option<&Values<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.data.get(key)
+    }
+
+    /// Returns one of the values for `key`, if there are any.
+    ///
+    /// See [`Values::get_one`].
+    pub fn get_one<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.data.get(key)?.get_one()
+    }
+
+    /// Returns true if the map contains `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.data.contains_key(key)
+    }
+}
+
+impl<K, V> SplitMultiMap<K, V> {
+    /// Returns the number of keys in the map.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if the map contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Iterate over every key and its bag of values, in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<'_, K, Values<V>> {
+        self.data.iter()
+    }
+
+    /// Iterate over all the keys in the map, in arbitrary order.
+    pub fn keys(&self) -> hash_map::Keys<'_, K, Values<V>> {
+        self.data.keys()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SplitMultiMap<K, V> {
+    type Item = (&'a K, &'a Values<V>);
+    type IntoIter = hash_map::Iter<'a, K, Values<V>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub(crate) enum Operation<K, V> {
+    Add(K, Aliased<V, NoDrop>),
+    RemoveValue(K, V),
+    Empty(K),
+    RemoveEntry(K),
+}
+
+impl<K, V> fmt::Debug for Operation<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Add(k, v) => f.debug_tuple("Add").field(k).field(v).finish(),
+            Operation::RemoveValue(k, v) => f.debug_tuple("RemoveValue").field(k).field(v).finish(),
+            Operation::Empty(k) => f.debug_tuple("Empty").field(k).finish(),
+            Operation::RemoveEntry(k) => f.debug_tuple("RemoveEntry").field(k).finish(),
+        }
+    }
+}
+
+/// Drop values that the last copy to absorb an operation let go of.
+///
+/// # Safety
+///
+/// Must only be called from [`Absorb::absorb_second`] or [`Absorb::drop_second`], at which
+/// point no other copy references the values.
+unsafe fn release<V>(values: impl IntoIterator<Item = Aliased<V, NoDrop>>) {
+    for value in values {
+        drop(value.change_drop::<DoDrop>());
+    }
+}
+
+impl<K, V> Absorb<Operation<K, V>> for SplitMultiMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Hash,
+{
+    fn absorb_first(&mut self, operation: &mut Operation<K, V>, _: &Self) {
+        // any values released here are still in the bags of the copies that have yet to see the
+        // operation, or owned by the operation itself, so they are simply forgotten.
+        match operation {
+            Operation::Add(key, value) => {
+                self.data
+                    .entry(key.clone())
+                    .or_default()
+                    .insert(unsafe { value.alias() });
+            }
+            Operation::RemoveValue(key, value) => {
+                if let Some(values) = self.data.get_mut(key) {
+                    values.remove(value);
+                }
+            }
+            Operation::Empty(key) => {
+                if let Some(values) = self.data.get_mut(key) {
+                    values.drain();
+                }
+            }
+            Operation::RemoveEntry(key) => {
+                self.data.remove(key);
+            }
+        }
+    }
+
+    fn absorb_second(&mut self, operation: Operation<K, V>, _: &Self) {
+        // safety: every other copy has already absorbed the operation, so a value that a bag
+        // lets go of here, whether it is a surplus duplicate, the value removed, or one of the
+        // values of an emptied or removed key, has left the matching bag in every copy. the
+        // writer has also waited for readers of this copy to leave before writing to it.
+        match operation {
+            Operation::Add(key, value) => unsafe {
+                release(self.data.entry(key).or_default().insert(value));
+            },
+            Operation::RemoveValue(key, value) => {
+                if let Some(values) = self.data.get_mut(&key) {
+                    unsafe { release(values.remove(&value)) };
+                }
+            }
+            Operation::Empty(key) => {
+                if let Some(values) = self.data.get_mut(&key) {
+                    unsafe { release(values.drain()) };
+                }
+            }
+            Operation::RemoveEntry(key) => {
+                if let Some(mut values) = self.data.remove(&key) {
+                    unsafe { release(values.drain()) };
+                }
+            }
+        }
+    }
+
+    fn drop_op(operation: Operation<K, V>) {
+        // the operation was never absorbed, so it holds the only alias of its value.
+        if let Operation::Add(_, value) = operation {
+            drop(unsafe { value.change_drop::<DoDrop>() });
+        }
+    }
+
+    fn drop_second(self: Box<Self>) {
+        let mut this = self;
+        for (_, mut values) in this.data.drain() {
+            unsafe { release(values.drain()) };
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        assert_eq!(self.data.len(), 0);
+        self.data.extend(
+            first
+                .data
+                .iter()
+                .map(|(k, vs)| (k.clone(), unsafe { vs.alias() })),
+        );
+    }
+}
+
+read_handle! {
+    /// A handle that may be used to read from a [`SplitMultiMap`].
+    ///
+    /// Like [`crate::ReadHandle`], each thread should use its own handle; clone one per thread.
+    pub struct ReadHandle<K, V>(SplitMultiMap<K, V>);
+}
+
+impl<K, V> ReadHandle<K, V> {
+    /// Take a snapshot of the published map.
+    ///
+    /// Returns `None` if the [`WriteHandle`] has been dropped.
+    pub fn enter(&self) -> Option<ReadGuard<'_, SplitMultiMap<K, V>>> {
+        self.handle.enter()
+    }
+
+    /// Returns the number of published keys in the map.
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |m| m.len())
+    }
+
+    /// Returns true if the published map has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.enter().is_none_or(|m| m.is_empty())
+    }
+}
+
+impl<K, V> ReadHandle<K, V>
+where
+    K: Eq + Hash,
+{
+    /// Returns a guarded reference to the published bag of values for `key`, if any.
+    pub fn get<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, Values<V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        ReadGuard::try_map(self.enter()?, |m| m.get(key))
+    }
+
+    /// Returns a guarded reference to one of the published values for `key`, if any.
+    ///
+    /// See [`Values::get_one`].
+    pub fn get_one<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        ReadGuard::try_map(self.enter()?, |m| m.get_one(key))
+    }
+
+    /// Returns true if the published map contains `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.enter().is_some_and(|m| m.contains_key(key))
+    }
+}
+
+write_handle! {
+    /// A handle that may be used to modify a [`SplitMultiMap`].
+    ///
+    /// Modifications are only visible to readers after [`publish`](Self::publish). The handle
+    /// dereferences to a [`ReadHandle`] that sees the published map.
+    pub struct WriteHandle<K, V>(SplitMultiMap<K, V>, Operation<K, V>)
+    where
+        K: Eq + Hash + Clone,
+        V: Eq + Hash,
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Hash,
+{
+    /// Add `value` to the bag of values for `key`.
+    pub fn insert(&mut self, key: K, value: V) -> &mut Self {
+        self.handle
+            .append(Operation::Add(key, Aliased::from(value)));
+        self
+    }
+
+    /// Remove one occurrence of `value` from the bag of values for `key`.
+    pub fn remove_value(&mut self, key: K, value: V) -> &mut Self {
+        self.handle.append(Operation::RemoveValue(key, value));
+        self
+    }
+
+    /// Remove all values for `key`, but keep the key in the map with an empty bag.
+    pub fn empty(&mut self, key: K) -> &mut Self {
+        self.handle.append(Operation::Empty(key));
+        self
+    }
+
+    /// Remove `key` and all of its values from the map.
+    pub fn remove_entry(&mut self, key: K) -> &mut Self {
+        self.handle.append(Operation::RemoveEntry(key));
+        self
+    }
+}
+
+/// Create an empty multi-value map, returning its write handle and a read handle.
+pub fn new<K, V>() -> (WriteHandle<K, V>, ReadHandle<K, V>)
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Hash,
+{
+    let (w, r) = crate::new();
+    WriteHandle::from_handles(w, r)
+}
@@ -0,0 +1,213 @@
+use crate::aliasing::{Aliased, NoDrop};
+use smallvec::SmallVec;
+use std::collections::{hash_map, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+/// Bags with more values than this are moved from a vector into a hash bag, so that removing a
+/// single value does not require a linear scan.
+const BAG_THRESHOLD: usize = 32;
+
+/// The bag of values stored for a single key in a [`SplitMultiMap`](super::SplitMultiMap).
+///
+/// Values may appear more than once. Small bags keep their values in insertion order (until a
+/// value is removed), large bags are unordered.
+pub struct Values<V>(ValuesInner<V>);
+
+enum ValuesInner<V> {
+    Short(SmallVec<[Aliased<V, NoDrop>; 1]>),
+    Long(HashMap<Aliased<V, NoDrop>, usize>),
+}
+
+impl<V> Default for Values<V> {
+    fn default() -> Self {
+        Values(ValuesInner::Short(SmallVec::new()))
+    }
+}
+
+impl<V> fmt::Debug for Values<V>
+where
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<V> Values<V> {
+    /// Returns the number of values in the bag, counting duplicates.
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            ValuesInner::Short(v) => v.len(),
+            ValuesInner::Long(v) => v.values().sum(),
+        }
+    }
+
+    /// Returns true if the bag holds no values.
+    pub fn is_empty(&self) -> bool {
+        match &self.0 {
+            ValuesInner::Short(v) => v.is_empty(),
+            ValuesInner::Long(v) => v.is_empty(),
+        }
+    }
+
+    /// Returns one of the values in the bag, if there are any.
+    ///
+    /// For small bags this is the oldest value. Which value is returned for large bags is
+    /// unspecified.
+    pub fn get_one(&self) -> Option<&V> {
+        match &self.0 {
+            ValuesInner::Short(v) => v.first().map(|v| &**v),
+            ValuesInner::Long(v) => v.keys().next().map(|v| &**v),
+        }
+    }
+
+    /// Iterate over the values in the bag, yielding duplicates once per occurrence.
+    pub fn iter(&self) -> ValuesIter<'_, V> {
+        match &self.0 {
+            ValuesInner::Short(v) => ValuesIter(ValuesIterInner::Short(v.iter())),
+            ValuesInner::Long(v) => ValuesIter(ValuesIterInner::Long(v.iter(), None)),
+        }
+    }
+}
+
+impl<V> Values<V>
+where
+    V: Eq + Hash,
+{
+    /// Returns true if `value` is in the bag.
+    pub fn contains(&self, value: &V) -> bool {
+        match &self.0 {
+            ValuesInner::Short(v) => v.iter().any(|v| **v == *value),
+            ValuesInner::Long(v) => v.contains_key(value),
+        }
+    }
+
+    /// Add `value` to the bag.
+    ///
+    /// Returns the values that the bag let go of in the process. A large bag only keeps one
+    /// instance of equal values around, so adding a duplicate to it, or growing a small bag into
+    /// a large one, releases the surplus instances.
+    pub(super) fn insert(&mut self, value: Aliased<V, NoDrop>) -> Vec<Aliased<V, NoDrop>> {
+        match &mut self.0 {
+            ValuesInner::Short(v) => {
+                v.push(value);
+                if v.len() <= BAG_THRESHOLD {
+                    return Vec::new();
+                }
+
+                // every copy promotes at the same operation, and always keeps the earliest of a
+                // set of equal values, so they end up keeping the same instances.
+                let mut released = Vec::new();
+                let mut bag = HashMap::with_capacity(v.len());
+                for value in v.drain(..) {
+                    if let Some(n) = bag.get_mut(&*value) {
+                        *n += 1;
+                        released.push(value);
+                    } else {
+                        bag.insert(value, 1);
+                    }
+                }
+                self.0 = ValuesInner::Long(bag);
+                released
+            }
+            ValuesInner::Long(v) => match v.get_mut(&*value) {
+                Some(n) => {
+                    *n += 1;
+                    vec![value]
+                }
+                None => {
+                    v.insert(value, 1);
+                    Vec::new()
+                }
+            },
+        }
+    }
+
+    /// Remove one occurrence of `value` from the bag, returning it if the bag let go of it.
+    pub(super) fn remove(&mut self, value: &V) -> Option<Aliased<V, NoDrop>> {
+        match &mut self.0 {
+            ValuesInner::Short(v) => {
+                let i = v.iter().position(|v| **v == *value)?;
+                Some(v.swap_remove(i))
+            }
+            ValuesInner::Long(v) => {
+                let n = v.get_mut(value)?;
+                if *n > 1 {
+                    *n -= 1;
+                    None
+                } else {
+                    v.remove_entry(value).map(|(v, _)| v)
+                }
+            }
+        }
+    }
+}
+
+impl<V> Values<V> {
+    /// Remove every value from the bag, returning the instances it held.
+    pub(super) fn drain(&mut self) -> Vec<Aliased<V, NoDrop>> {
+        match &mut self.0 {
+            ValuesInner::Short(v) => v.drain(..).collect(),
+            ValuesInner::Long(v) => v.drain().map(|(v, _)| v).collect(),
+        }
+    }
+
+    /// Make a bag holding aliases of every value in `self`.
+    ///
+    /// # Safety
+    ///
+    /// See [`Aliased::alias`].
+    pub(super) unsafe fn alias(&self) -> Self
+    where
+        V: Eq + Hash,
+    {
+        Values(match &self.0 {
+            ValuesInner::Short(v) => ValuesInner::Short(v.iter().map(|v| v.alias()).collect()),
+            ValuesInner::Long(v) => {
+                ValuesInner::Long(v.iter().map(|(v, &n)| (v.alias(), n)).collect())
+            }
+        })
+    }
+}
+
+impl<'a, V> IntoIterator for &'a Values<V> {
+    type Item = &'a V;
+    type IntoIter = ValuesIter<'a, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the values in a [`Values`] bag.
+#[derive(Debug)]
+pub struct ValuesIter<'a, V>(ValuesIterInner<'a, V>);
+
+#[derive(Debug)]
+enum ValuesIterInner<'a, V> {
+    Short(std::slice::Iter<'a, Aliased<V, NoDrop>>),
+    Long(
+        hash_map::Iter<'a, Aliased<V, NoDrop>, usize>,
+        Option<(&'a V, usize)>,
+    ),
+}
+
+impl<'a, V> Iterator for ValuesIter<'a, V> {
+    type Item = &'a V;
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            ValuesIterInner::Short(it) => it.next().map(|v| &**v),
+            ValuesIterInner::Long(it, current) => {
+                if let Some((v, n)) = current {
+                    if *n > 0 {
+                        *n -= 1;
+                        return Some(*v);
+                    }
+                }
+                let (v, &n) = it.next()?;
+                *current = Some((&**v, n - 1));
+                Some(&**v)
+            }
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{Absorb, WriteHandle};
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError, SendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// When an [`AutoPublisher`] publishes the operations it has received.
+///
+/// Whatever the policy, an [`AutoPublisher`] also publishes when asked to with
+/// [`Submitter::publish`], and when it is stopped.
+pub enum PublishPolicy<O> {
+    /// Only publish when asked to.
+    Manual,
+    /// Publish once this many operations are pending.
+    EveryOps(usize),
+    /// Publish pending operations at most this long after the first of them arrived.
+    Every(Duration),
+    /// Publish once pending operations take up this many bytes.
+    ///
+    /// `size_of` gives the size of each operation, for example including the heap memory it
+    /// owns, or its length once serialized.
+    OplogBytes {
+        /// The number of bytes to publish at.
+        bytes: usize,
+        /// The size of a single operation.
+        size_of: fn(&O) -> usize,
+    },
+}
+
+impl<O> fmt::Debug for PublishPolicy<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishPolicy::Manual => f.write_str("Manual"),
+            PublishPolicy::EveryOps(n) => f.debug_tuple("EveryOps").field(n).finish(),
+            PublishPolicy::Every(d) => f.debug_tuple("Every").field(d).finish(),
+            PublishPolicy::OplogBytes { bytes, .. } => f
+                .debug_struct("OplogBytes")
+                .field("bytes", bytes)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<O> Clone for PublishPolicy<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<O> Copy for PublishPolicy<O> {}
+
+impl<O> PublishPolicy<O> {
+    /// The size of `op`, as far as this policy is concerned.
+    fn size_of(&self, op: &O) -> usize {
+        match *self {
+            PublishPolicy::OplogBytes { size_of, .. } => size_of(op),
+            _ => 0,
+        }
+    }
+
+    fn is_due(&self, pending: usize, bytes: usize, since: Instant) -> bool {
+        match *self {
+            PublishPolicy::Manual => false,
+            PublishPolicy::EveryOps(n) => pending >= n,
+            PublishPolicy::Every(d) => since.elapsed() >= d,
+            PublishPolicy::OplogBytes { bytes: limit, .. } => bytes >= limit,
+        }
+    }
+}
+
+enum Message<O> {
+    Op(O),
+    Publish(mpsc::Sender<()>),
+    Stop,
+}
+
+/// A [`WriteHandle`] owned by a background thread, which publishes operations sent to it
+/// according to a [`PublishPolicy`].
+///
+/// Created with [`WriteHandle::into_auto_publisher`]. Operations are sent through
+/// [`Submitter`]s. Dropping the `AutoPublisher` publishes any remaining operations and then
+/// drops the `WriteHandle`; use [`stop`](Self::stop) to get the handle back instead.
+pub struct AutoPublisher<T, O>
+where
+    T: Absorb<O>,
+{
+    tx: mpsc::Sender<Message<O>>,
+    thread: Option<thread::JoinHandle<WriteHandle<T, O>>>,
+}
+
+impl<T, O> fmt::Debug for AutoPublisher<T, O>
+where
+    T: Absorb<O>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoPublisher")
+            .field("thread", &self.thread)
+            .finish()
+    }
+}
+
+/// A cloneable handle for sending operations to an [`AutoPublisher`].
+pub struct Submitter<O> {
+    tx: mpsc::Sender<Message<O>>,
+}
+
+impl<O> fmt::Debug for Submitter<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Submitter").finish_non_exhaustive()
+    }
+}
+
+impl<O> Clone for Submitter<O> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<O> Submitter<O> {
+    /// Send an operation to be published.
+    ///
+    /// Returns the operation back if the [`AutoPublisher`] has stopped.
+    pub fn submit(&self, op: O) -> Result<(), SendError<O>> {
+        self.tx.send(Message::Op(op)).map_err(|e| match e.0 {
+            Message::Op(op) => SendError(op),
+            _ => unreachable!("we sent an operation"),
+        })
+    }
+
+    /// Publish all operations submitted so far, and wait until they are visible to readers.
+    ///
+    /// Fails if the [`AutoPublisher`] has stopped.
+    pub fn publish(&self) -> Result<(), SendError<()>> {
+        let (done, published) = mpsc::channel();
+        self.tx
+            .send(Message::Publish(done))
+            .map_err(|_| SendError(()))?;
+        published.recv().map_err(|_| SendError(()))
+    }
+}
+
+impl<T, O> WriteHandle<T, O>
+where
+    T: Absorb<O> + Send + Sync + 'static,
+    O: Send + 'static,
+{
+    /// Move this handle onto a background thread that publishes according to `policy`.
+    ///
+    /// Operations are sent to the thread through the [`Submitter`]s of the returned
+    /// [`AutoPublisher`], and applied in the order the thread receives them.
+    pub fn into_auto_publisher(self, policy: PublishPolicy<O>) -> AutoPublisher<T, O> {
+        let (tx, rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("splitwrite-publisher".to_string())
+            .spawn(move || run(self, policy, rx))
+            .expect("failed to spawn publisher thread");
+        AutoPublisher {
+            tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T, O> AutoPublisher<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Get a new handle for submitting operations.
+    pub fn submitter(&self) -> Submitter<O> {
+        Submitter {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Stop the background thread, and return the `WriteHandle` once it has published all
+    /// operations received before this call.
+    ///
+    /// Operations submitted after this are rejected.
+    pub fn stop(mut self) -> WriteHandle<T, O> {
+        self.stop_inner().expect("only stopped once")
+    }
+
+    fn stop_inner(&mut self) -> Option<WriteHandle<T, O>> {
+        let thread = self.thread.take()?;
+        // the thread only goes away by receiving this, or by panicking.
+        let _ = self.tx.send(Message::Stop);
+        match thread.join() {
+            Ok(w) => Some(w),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<T, O> Drop for AutoPublisher<T, O>
+where
+    T: Absorb<O>,
+{
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            drop(self.stop_inner());
+        } else if self.thread.take().is_some() {
+            // still tell the thread to publish what it has and stop, but do not wait for it, or
+            // bring its panic into ours.
+            let _ = self.tx.send(Message::Stop);
+        }
+    }
+}
+
+fn run<T, O>(
+    mut w: WriteHandle<T, O>,
+    policy: PublishPolicy<O>,
+    rx: mpsc::Receiver<Message<O>>,
+) -> WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    let mut pending = 0;
+    let mut bytes = 0;
+    let mut since = Instant::now();
+    loop {
+        let msg = match policy {
+            PublishPolicy::Every(d) if pending != 0 => {
+                match rx.recv_timeout(d.saturating_sub(since.elapsed())) {
+                    Ok(msg) => Some(msg),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            _ => match rx.recv() {
+                Ok(msg) => Some(msg),
+                Err(_) => break,
+            },
+        };
+
+        match msg {
+            Some(Message::Op(op)) => {
+                if pending == 0 {
+                    since = Instant::now();
+                }
+                bytes += policy.size_of(&op);
+                w.append(op);
+                pending += 1;
+            }
+            Some(Message::Publish(done)) => {
+                w.publish();
+                pending = 0;
+                bytes = 0;
+                let _ = done.send(());
+                continue;
+            }
+            Some(Message::Stop) => break,
+            None => {}
+        }
+
+        if pending != 0 && policy.is_due(pending, bytes, since) {
+            w.publish();
+            pending = 0;
+            bytes = 0;
+        }
+    }
+
+    // pick up anything that was sent before we were told to stop.
+    while let Ok(msg) = rx.try_recv() {
+        match msg {
+            Message::Op(op) => {
+                w.append(op);
+                pending += 1;
+            }
+            Message::Publish(done) => {
+                w.publish();
+                pending = 0;
+                let _ = done.send(());
+            }
+            Message::Stop => {}
+        }
+    }
+    if pending != 0 {
+        w.publish();
+    }
+    w
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublishPolicy;
+    use crate::{Applied, Apply};
+    use std::time::Duration;
+
+    fn add(n: i32) -> Apply<i32> {
+        Apply::new(move |x| *x += n)
+    }
+
+    #[test]
+    fn every_ops() {
+        let (w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let publisher = w.into_auto_publisher(PublishPolicy::EveryOps(2));
+        let submitter = publisher.submitter();
+        for n in 1..=3 {
+            submitter.submit(add(n)).unwrap();
+        }
+        r.wait_for_generation(1);
+        assert_eq!(**r.enter().unwrap(), 3);
+
+        let w = publisher.stop();
+        assert_eq!(**r.enter().unwrap(), 6);
+        assert!(submitter.submit(add(1)).is_err());
+        assert!(submitter.publish().is_err());
+        assert!(!w.has_pending_operations());
+    }
+
+    #[test]
+    fn every_duration() {
+        let (w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let publisher = w.into_auto_publisher(PublishPolicy::Every(Duration::from_millis(10)));
+        publisher.submitter().submit(add(1)).unwrap();
+        assert_eq!(r.wait_for_generation(1), Some(1));
+        assert_eq!(**r.enter().unwrap(), 1);
+    }
+
+    #[test]
+    fn manual() {
+        let (w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let publisher = w.into_auto_publisher(PublishPolicy::Manual);
+        let submitter = publisher.submitter();
+        submitter.submit(add(1)).unwrap();
+        submitter.clone().submit(add(2)).unwrap();
+        assert_eq!(r.generation(), 0);
+        submitter.publish().unwrap();
+        assert_eq!(**r.enter().unwrap(), 3);
+
+        submitter.submit(add(3)).unwrap();
+        drop(publisher);
+        assert!(r.was_dropped());
+    }
+
+    #[test]
+    fn oplog_bytes() {
+        let (w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let publisher = w.into_auto_publisher(PublishPolicy::OplogBytes {
+            bytes: 100,
+            size_of: |_| 40,
+        });
+        let submitter = publisher.submitter();
+        submitter.submit(add(1)).unwrap();
+        submitter.submit(add(1)).unwrap();
+        submitter.submit(add(1)).unwrap();
+        r.wait_for_generation(1);
+        assert_eq!(**r.enter().unwrap(), 3);
+    }
+
+    #[test]
+    fn dropped_while_panicking() {
+        use std::panic::{self, AssertUnwindSafe};
+        use std::time::Instant;
+
+        let (w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let publisher = w.into_auto_publisher(PublishPolicy::Manual);
+        let submitter = publisher.submitter();
+        submitter.submit(add(1)).unwrap();
+        let outcome = panic::catch_unwind(AssertUnwindSafe(move || {
+            let _publisher = publisher;
+            panic!("halfway");
+        }));
+        assert!(outcome.is_err());
+
+        // the submitter keeps the channel open, so only the stop message ends the thread.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !r.was_dropped() {
+            assert!(Instant::now() < deadline, "the thread was never stopped");
+            std::thread::yield_now();
+        }
+        assert!(submitter.submit(add(1)).is_err());
+    }
+}
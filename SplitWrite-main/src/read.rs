@@ -1,4 +1,6 @@
-use crate::sync::{fence, Arc, AtomicPtr, AtomicUsize, Ordering};
+use crate::generation::Generation;
+use crate::sync::{Arc, AtomicPtr, AtomicUsize, CachePadded, Ordering};
+use crate::wakeup::Wakeup;
 use std::cell::Cell;
 use std::fmt;
 use std::marker::PhantomData;
@@ -13,12 +15,23 @@ pub use guard::ReadGuard;
 mod factory;
 pub use factory::ReadHandleFactory;
 
+mod shared;
+pub use shared::SharedReadHandle;
+
+/// A read handle to a left-right guarded data structure.
+///
+/// Each handle tracks the reads it performs with its own epoch counter, so a handle cannot be
+/// shared between threads. Use [`Clone`] or a [`ReadHandleFactory`] to get one per thread.
 pub struct ReadHandle<T> {
-    pub(crate) inner: Arc<AtomicPtr<T>>,
+    pub(crate) inner: Arc<CachePadded<AtomicPtr<T>>>,
     pub(crate) epochs: crate::Epochs,
-    epoch: Arc<AtomicUsize>,
+    pub(crate) wakeup: Arc<Wakeup>,
+    pub(crate) generation: Arc<Generation>,
+    epoch: NonNull<AtomicUsize>,
     epoch_i: usize,
     enters: Cell<usize>,
+    #[cfg(feature = "async")]
+    seen: Cell<u64>,
 
     _unimpl_send: PhantomData<*const T>,
 }
@@ -26,9 +39,8 @@ unsafe impl<T> Send for ReadHandle<T> where T: Sync {}
 
 impl<T> Drop for ReadHandle<T> {
     fn drop(&mut self) {
-        let e = self.epochs.lock().unwrap().remove(self.epoch_i);
-        assert!(Arc::ptr_eq(&e, &self.epoch));
         assert_eq!(self.enters.get(), 0);
+        self.epochs.release(self.epoch_i);
     }
 }
 
@@ -36,32 +48,49 @@ impl<T> fmt::Debug for ReadHandle<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ReadHandle")
             .field("epochs", &self.epochs)
-            .field("epoch", &self.epoch)
+            .field("epoch", self.epoch())
             .finish()
     }
 }
 
 impl<T> Clone for ReadHandle<T> {
     fn clone(&self) -> Self {
-        ReadHandle::new_with_arc(Arc::clone(&self.inner), Arc::clone(&self.epochs))
+        ReadHandle::new_with_arc(
+            Arc::clone(&self.inner),
+            Arc::clone(&self.epochs),
+            Arc::clone(&self.wakeup),
+            Arc::clone(&self.generation),
+        )
     }
 }
 
 impl<T> ReadHandle<T> {
     pub(crate) fn new(inner: T, epochs: crate::Epochs) -> Self {
         let store = Box::into_raw(Box::new(inner));
-        let inner = Arc::new(AtomicPtr::new(store));
-        Self::new_with_arc(inner, epochs)
+        let inner = Arc::new(CachePadded(AtomicPtr::new(store)));
+        Self::new_with_arc(
+            inner,
+            epochs,
+            Arc::new(Wakeup::default()),
+            Arc::new(Generation::default()),
+        )
     }
 
-    fn new_with_arc(inner: Arc<AtomicPtr<T>>, epochs: crate::Epochs) -> Self {
-      
-        let epoch = Arc::new(AtomicUsize::new(0));
-      
-        let epoch_i = epochs.lock().unwrap().insert(Arc::clone(&epoch));
+    fn new_with_arc(
+        inner: Arc<CachePadded<AtomicPtr<T>>>,
+        epochs: crate::Epochs,
+        wakeup: Arc<Wakeup>,
+        generation: Arc<Generation>,
+    ) -> Self {
+        let epoch_i = epochs.register();
+        let epoch = NonNull::from(epochs.epoch(epoch_i));
 
         Self {
             epochs,
+            wakeup,
+            #[cfg(feature = "async")]
+            seen: Cell::new(generation.current()),
+            generation,
             epoch,
             epoch_i,
             enters: Cell::new(0),
@@ -70,15 +99,33 @@ impl<T> ReadHandle<T> {
         }
     }
 
+    pub(super) fn epoch(&self) -> &AtomicUsize {
+        // Safety: slots are not freed until the registry is, and we hold on to the registry.
+        unsafe { self.epoch.as_ref() }
+    }
+
+    /// Create a [`SharedReadHandle`], which is `Sync` and keeps a read handle per thread.
+    pub fn shared(&self) -> SharedReadHandle<T> {
+        SharedReadHandle::from(self.factory())
+    }
+
+    /// Create a [`ReadHandleFactory`], which is `Sync` and can produce new read handles.
     pub fn factory(&self) -> ReadHandleFactory<T> {
         ReadHandleFactory {
             inner: Arc::clone(&self.inner),
             epochs: Arc::clone(&self.epochs),
+            wakeup: Arc::clone(&self.wakeup),
+            generation: Arc::clone(&self.generation),
         }
     }
 }
 
 impl<T> ReadHandle<T> {
+    /// Take a snapshot of the published data.
+    ///
+    /// The writer cannot modify the copy being read until the returned [`ReadGuard`] is
+    /// dropped, so guards should be short-lived. Returns `None` if the [`WriteHandle`] has
+    /// been dropped.
     pub fn enter(&self) -> Option<ReadGuard<'_, T>> {
         let enters = self.enters.get();
         if enters != 0 {
@@ -88,7 +135,7 @@ impl<T> ReadHandle<T> {
             return if let Some(r_handle) = r_handle {
                 self.enters.set(enters + 1);
                 Some(ReadGuard {
-                    handle: guard::ReadHandleState::from(self),
+                    handle: guard::ReadHandleState::new(self, r_handle),
                     t: r_handle,
                 })
             } else {
@@ -96,34 +143,82 @@ impl<T> ReadHandle<T> {
             };
         }
 
-        self.epoch.fetch_add(1, Ordering::AcqRel);
-        fence(Ordering::SeqCst);
-
-        let r_handle = self.inner.load(Ordering::Acquire);
+        // a SeqCst increment followed by a SeqCst load is ordered against the fence in
+        // `publish` without a (much more expensive) fence of our own: either the writer sees our
+        // epoch go odd, or we see the copy it just published.
+        self.epoch().fetch_add(1, Ordering::SeqCst);
+        // loom treats SeqCst accesses as AcqRel, and so cannot follow the argument above. give
+        // it the fence that the argument amounts to, so that it checks everything else.
+        #[cfg(loom)]
+        crate::sync::fence(Ordering::SeqCst);
+        let r_handle = self.inner.load(Ordering::SeqCst);
         let r_handle = unsafe { r_handle.as_ref() };
 
         if let Some(r_handle) = r_handle {
             let enters = self.enters.get() + 1;
             self.enters.set(enters);
             Some(ReadGuard {
-                handle: guard::ReadHandleState::from(self),
+                handle: guard::ReadHandleState::new(self, r_handle),
                 t: r_handle,
             })
         } else {
-            self.epoch.fetch_add(1, Ordering::AcqRel);
+            self.epoch().fetch_add(1, Ordering::AcqRel);
             None
         }
     }
 
+    /// Returns how many times the [`WriteHandle`] has published.
+    ///
+    /// A publish is counted once its changes are visible to [`enter`](Self::enter).
+    pub fn generation(&self) -> u64 {
+        self.generation.current()
+    }
+
+    /// Block until the [`generation`](Self::generation) is at least `g`, and return it.
+    ///
+    /// Returns `None` if the [`WriteHandle`] is dropped before then.
+    pub fn wait_for_generation(&self, g: u64) -> Option<u64> {
+        self.generation.wait_for(g)
+    }
+
+    /// Wait for a publish this handle has not yet seen through `changed`, and return the new
+    /// [`generation`](Self::generation).
+    ///
+    /// The first call waits for a publish that happens after the handle was created. Like a
+    /// `watch` channel, several publishes between two calls are reported only once. Returns
+    /// `None` if the [`WriteHandle`] is dropped before the next publish.
+    #[cfg(feature = "async")]
+    pub async fn changed(&self) -> Option<u64> {
+        let g =
+            std::future::poll_fn(|cx| self.generation.poll_for(self.seen.get() + 1, cx.waker()))
+                .await?;
+        self.seen.set(g);
+        Some(g)
+    }
+
+    /// Take a snapshot of data that is at least at `version`, blocking until it is published.
+    ///
+    /// This lets a reader observe a write it knows was published, for example by passing along
+    /// [`WriteHandle::published_version`]. Returns `None` if the [`WriteHandle`] is dropped
+    /// before then.
+    pub fn enter_at_least(&self, version: u64) -> Option<ReadGuard<'_, T>> {
+        self.wait_for_generation(version)?;
+        self.enter()
+    }
+
+    /// Returns true if the [`WriteHandle`] has been dropped.
     pub fn was_dropped(&self) -> bool {
         self.inner.load(Ordering::Acquire).is_null()
     }
 
+    /// Returns a raw pointer to the published copy of the data.
+    ///
+    /// Nothing prevents the writer from modifying the data behind the pointer, so it is only
+    /// safe to dereference while a [`ReadGuard`] from this handle is held.
     pub fn raw_handle(&self) -> Option<NonNull<T>> {
         NonNull::new(self.inner.load(Ordering::Acquire))
     }
 }
 
-
 #[allow(dead_code)]
 struct CheckReadHandleSendNotSync;
@@ -1,10 +1,17 @@
 use super::ReadHandle;
-use crate::sync::{Arc, AtomicPtr};
+use crate::generation::Generation;
+use crate::sync::{Arc, AtomicPtr, CachePadded};
+use crate::wakeup::Wakeup;
 use std::fmt;
 
+/// A type that is both `Sync` and `Send` and lets you produce new [`ReadHandle`] instances.
+///
+/// Useful when you cannot clone a `ReadHandle` into every thread up front.
 pub struct ReadHandleFactory<T> {
-    pub(super) inner: Arc<AtomicPtr<T>>,
+    pub(super) inner: Arc<CachePadded<AtomicPtr<T>>>,
     pub(super) epochs: crate::Epochs,
+    pub(super) wakeup: Arc<Wakeup>,
+    pub(super) generation: Arc<Generation>,
 }
 
 impl<T> fmt::Debug for ReadHandleFactory<T> {
@@ -20,13 +27,20 @@ impl<T> Clone for ReadHandleFactory<T> {
         Self {
             inner: Arc::clone(&self.inner),
             epochs: Arc::clone(&self.epochs),
+            wakeup: Arc::clone(&self.wakeup),
+            generation: Arc::clone(&self.generation),
         }
     }
 }
 
 impl<T> ReadHandleFactory<T> {
-    
+    /// Produce a new [`ReadHandle`] to the same data.
     pub fn handle(&self) -> ReadHandle<T> {
-        ReadHandle::new_with_arc(Arc::clone(&self.inner), Arc::clone(&self.epochs))
+        ReadHandle::new_with_arc(
+            Arc::clone(&self.inner),
+            Arc::clone(&self.epochs),
+            Arc::clone(&self.wakeup),
+            Arc::clone(&self.generation),
+        )
     }
 }
@@ -1,4 +1,6 @@
+use crate::generation::Generation;
 use crate::sync::{AtomicUsize, Ordering};
+use crate::wakeup::Wakeup;
 use std::cell::Cell;
 use std::mem;
 
@@ -6,25 +8,35 @@ use std::mem;
 pub(super) struct ReadHandleState<'rh> {
     pub(super) epoch: &'rh AtomicUsize,
     pub(super) enters: &'rh Cell<usize>,
+    pub(super) wakeup: &'rh Wakeup,
+    pub(super) generation: &'rh Generation,
+    /// The address of the copy the guard was taken out on.
+    pub(super) copy: usize,
 }
 
-impl<'rh, T> From<&'rh super::ReadHandle<T>> for ReadHandleState<'rh> {
-    fn from(rh: &'rh super::ReadHandle<T>) -> Self {
+impl<'rh> ReadHandleState<'rh> {
+    pub(super) fn new<T>(rh: &'rh super::ReadHandle<T>, copy: &T) -> Self {
         Self {
-            epoch: &rh.epoch,
+            epoch: rh.epoch(),
             enters: &rh.enters,
+            wakeup: &rh.wakeup,
+            generation: &rh.generation,
+            copy: copy as *const T as usize,
         }
     }
 }
 
+/// A guard wrapping a live reference into a left-right guarded data structure.
+///
+/// While the guard is alive, the writer cannot modify the copy it points into.
 #[derive(Debug)]
 pub struct ReadGuard<'rh, T: ?Sized> {
-    
     pub(super) t: &'rh T,
     pub(super) handle: ReadHandleState<'rh>,
 }
 
 impl<'rh, T: ?Sized> ReadGuard<'rh, T> {
+    /// Make a new guard for a component of the borrowed data.
     pub fn map<F, U: ?Sized>(orig: Self, f: F) -> ReadGuard<'rh, U>
     where
         F: for<'a> FnOnce(&'a T) -> &'a U,
@@ -37,6 +49,7 @@ impl<'rh, T: ?Sized> ReadGuard<'rh, T> {
         rg
     }
 
+    /// Make a new guard for a component of the borrowed data, if that component exists.
     pub fn try_map<F, U: ?Sized>(orig: Self, f: F) -> Option<ReadGuard<'rh, U>>
     where
         F: for<'a> FnOnce(&'a T) -> Option<&'a U>,
@@ -48,6 +61,14 @@ impl<'rh, T: ?Sized> ReadGuard<'rh, T> {
         mem::forget(orig);
         Some(rg)
     }
+
+    /// The version of the data this guard is reading.
+    ///
+    /// This is the [`generation`](super::ReadHandle::generation) at the publish that made the
+    /// data visible, so a guard taken out before the first publish reads version 0.
+    pub fn version(&self) -> u64 {
+        self.handle.generation.version_of(self.handle.copy)
+    }
 }
 
 impl<'rh, T: ?Sized> AsRef<T> for ReadGuard<'rh, T> {
@@ -68,8 +89,10 @@ impl<'rh, T: ?Sized> Drop for ReadGuard<'rh, T> {
         let enters = self.handle.enters.get() - 1;
         self.handle.enters.set(enters);
         if enters == 0 {
-            
-            self.handle.epoch.fetch_add(1, Ordering::AcqRel);
+            // SeqCst so that either a parked writer sees the new epoch when it rechecks, or we
+            // see that it is waiting.
+            self.handle.epoch.fetch_add(1, Ordering::SeqCst);
+            self.handle.wakeup.notify();
         }
     }
 }
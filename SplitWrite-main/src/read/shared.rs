@@ -0,0 +1,224 @@
+use super::{ReadGuard, ReadHandle, ReadHandleFactory};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+thread_local! {
+    static HANDLES: RefCell<Vec<Local>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Hands out the ids that key [`SharedReadHandle`]s in each thread's `HANDLES`.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// This thread's read handle for the [`SharedReadHandle`] with the given id.
+struct Local {
+    id: u64,
+    /// Points into a box held by `owner`.
+    handle: *const (),
+    owner: Weak<dyn Owner>,
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        // the thread is exiting, or the owner is gone and took the handle with it.
+        if let Some(owner) = self.owner.upgrade() {
+            owner.release(self.handle);
+        }
+    }
+}
+
+/// The state behind a [`SharedReadHandle`], with the type of the data erased.
+trait Owner {
+    /// Drop the handle at `handle`, which belongs to a thread that is exiting.
+    fn release(&self, handle: *const ());
+}
+
+/// The state shared by the clones of a [`SharedReadHandle`].
+struct Shared<T> {
+    factory: ReadHandleFactory<T>,
+    id: u64,
+    /// The handle of every thread that has entered and not yet exited. They are kept here rather
+    /// than in the threads' locals, so that they can all be released as soon as the last clone
+    /// goes away, even from threads that never come back to this handle. Each is boxed so that
+    /// the threads can point to it.
+    #[allow(clippy::vec_box)]
+    handles: Mutex<Vec<Box<ReadHandle<T>>>>,
+}
+
+impl<T> Owner for Shared<T> {
+    fn release(&self, handle: *const ()) {
+        let mut handles = self.handles.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(i) = handles
+            .iter()
+            .position(|h| &**h as *const ReadHandle<T> as *const () == handle)
+        else {
+            return;
+        };
+        let handle = handles.swap_remove(i);
+        drop(handles);
+        // a guard that outlives this thread's locals still points into the handle.
+        if handle.enters.get() != 0 {
+            std::mem::forget(handle);
+        }
+    }
+}
+
+/// A read handle that can be shared between threads.
+///
+/// Each thread that calls [`enter`](Self::enter) gets its own [`ReadHandle`], created on first
+/// use. A thread's handle is released when the thread exits, or when the last clone of the
+/// `SharedReadHandle` is dropped, whichever comes first. This saves cloning a `ReadHandle` for
+/// every thread up front, at the cost of a short scan of a thread-local list on each `enter`.
+pub struct SharedReadHandle<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> fmt::Debug for SharedReadHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedReadHandle")
+            .field("factory", &self.shared.factory)
+            .finish()
+    }
+}
+
+impl<T> Clone for SharedReadHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> From<ReadHandleFactory<T>> for SharedReadHandle<T> {
+    fn from(factory: ReadHandleFactory<T>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                factory,
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                handles: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+}
+
+impl<T: 'static> SharedReadHandle<T> {
+    /// Take a snapshot of the published data through this thread's [`ReadHandle`].
+    ///
+    /// See [`ReadHandle::enter`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while this thread's thread-locals are being destroyed.
+    pub fn enter(&self) -> Option<ReadGuard<'_, T>> {
+        self.handle().enter()
+    }
+
+    /// Returns true if the [`WriteHandle`](crate::WriteHandle) has been dropped.
+    pub fn was_dropped(&self) -> bool {
+        self.handle().was_dropped()
+    }
+
+    /// Returns how many times the [`WriteHandle`](crate::WriteHandle) has published.
+    pub fn generation(&self) -> u64 {
+        self.handle().generation()
+    }
+
+    fn handle(&self) -> &ReadHandle<T> {
+        let id = self.shared.id;
+        let handle = HANDLES.with(|locals| {
+            if let Some(local) = locals.borrow().iter().find(|local| local.id == id) {
+                return local.handle;
+            }
+
+            let handle = Box::new(self.shared.factory.handle());
+            let ptr = &*handle as *const ReadHandle<T> as *const ();
+            self.shared
+                .handles
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(handle);
+            let owner: Arc<dyn Owner> = self.shared.clone();
+            let local = Local {
+                id,
+                handle: ptr,
+                owner: Arc::downgrade(&owner),
+            };
+
+            let mut locals = locals.borrow_mut();
+            // forget the handles of shared handles that are gone, which released them already.
+            locals.retain(|local| local.owner.strong_count() != 0);
+            locals.push(local);
+            ptr
+        });
+
+        // Safety: the handle is boxed, so it does not move when `handles` changes. It is only
+        // dropped when this thread exits, or when the last clone of `self` goes away, and the
+        // reference we return is tied to `self`.
+        unsafe { &*(handle as *const ReadHandle<T>) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Applied, Apply};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn one_handle_per_thread() {
+        let (mut w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        w.apply(|x| *x = 42);
+        w.publish();
+
+        let shared = Arc::new(r.shared());
+        let reader = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let a = shared.enter().unwrap();
+                let b = shared.enter().unwrap();
+                assert_eq!((**a, **b), (42, 42));
+                drop((a, b));
+                // both enters went through the same handle, which stays until the thread exits.
+                assert_eq!(shared.shared.factory.epochs.len(), 3);
+            })
+        };
+        reader.join().unwrap();
+        assert_eq!(r.epochs.len(), 2);
+
+        // entering from this thread registers a handle that lives as long as the thread.
+        assert_eq!(**shared.enter().unwrap(), 42);
+        assert_eq!(r.epochs.len(), 3);
+        drop(w);
+        assert!(shared.was_dropped());
+    }
+
+    #[test]
+    fn dropping_the_last_clone_releases_every_thread() {
+        use std::sync::mpsc;
+
+        let (_w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let shared = r.shared();
+        let (entered, entered_rx) = mpsc::channel();
+        let (done, done_rx) = mpsc::channel::<()>();
+        let pool = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                assert!(shared.enter().is_some());
+                drop(shared);
+                entered.send(()).unwrap();
+                // a pool thread that lingers without touching the handle again.
+                done_rx.recv().unwrap();
+            })
+        };
+        entered_rx.recv().unwrap();
+        assert!(shared.enter().is_some());
+        assert_eq!(r.epochs.len(), 4);
+
+        drop(shared);
+        assert_eq!(r.epochs.len(), 2);
+        done.send(()).unwrap();
+        pool.join().unwrap();
+        assert_eq!(r.epochs.len(), 2);
+    }
+}
@@ -0,0 +1,435 @@
+//! Read replicas in other processes, with the `replication` feature.
+//!
+//! A [`Leader`] wraps a [`WriteHandle`](crate::WriteHandle), and sends every batch of
+//! operations it publishes to its followers over a byte stream, typically a `UnixStream` or
+//! `TcpStream`. A [`Follower`], created with [`follow`], owns a write handle of its own, and
+//! applies each batch it receives with a publish of its own, so its readers see exactly the
+//! states the leader's readers saw, a little later.
+//!
+//! A follower can join at any time. The leader starts every new follower off with a snapshot of
+//! its published data, and sends it the batches published after that.
+//!
+//! Publishing never waits for followers. Each follower has a queue of messages that a thread of
+//! its own writes to its stream, and a follower that falls more than
+//! [`Leader::set_max_backlog`] batches behind, or whose stream fails, is disconnected. The
+//! followers dropped this way are reported by the next [`Leader::publish`], and can join again
+//! from a fresh snapshot.
+//!
+//! # Protocol
+//!
+//! The leader first sends a 4-byte magic and a protocol version. Each message after that starts
+//! with a byte giving its kind:
+//!
+//! - a snapshot, as written by [`ReadHandle::snapshot_to`], whose position is the number of the
+//!   first batch to follow it, sent once when the follower joins;
+//! - a batch: its number, the length and CRC-32 of its contents, and then the operations
+//!   serialized with `bincode`.
+//!
+//! Batches are numbered one after another, and a follower rejects a batch out of order. Every
+//! publish is sent as a batch, even one with no operations in it, so a follower publishes exactly
+//! as many times as its leader.
+
+use crate::snapshot::{self, invalid};
+use crate::{Absorb, ReadHandle, Savepoint, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::{fmt, ops, thread};
+
+const MAGIC: &[u8; 4] = b"SWRP";
+const PROTOCOL: u32 = 1;
+
+const SNAPSHOT: u8 = 0;
+const BATCH: u8 = 1;
+
+/// The default for [`Leader::set_max_backlog`].
+const MAX_BACKLOG: usize = 1024;
+
+/// Identifies a follower of a [`Leader`], as returned by [`Leader::add_follower`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FollowerId(u64);
+
+/// The leader's end of a follower's queue.
+struct Link {
+    id: FollowerId,
+    queue: SyncSender<Arc<[u8]>>,
+}
+
+impl Link {
+    /// Start a thread that writes everything sent to the queue to `stream`, starting with
+    /// `greeting`.
+    fn spawn<S>(
+        id: FollowerId,
+        mut stream: S,
+        greeting: Vec<u8>,
+        backlog: usize,
+    ) -> io::Result<Self>
+    where
+        S: Write + Send + 'static,
+    {
+        let (queue, messages) = mpsc::sync_channel::<Arc<[u8]>>(backlog + 1);
+        queue
+            .try_send(greeting.into())
+            .expect("a new queue has room for the greeting");
+        thread::Builder::new()
+            .name("splitwrite-follower".to_string())
+            .spawn(move || {
+                // a write that fails drops the receiver, which the leader sees at its next send.
+                for message in messages {
+                    if stream
+                        .write_all(&message)
+                        .and_then(|_| stream.flush())
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            })?;
+        Ok(Self { id, queue })
+    }
+}
+
+/// A [`WriteHandle`](crate::WriteHandle) that sends what it publishes to followers.
+///
+/// Everything but publishing works as on the inner handle.
+pub struct Leader<T, O>
+where
+    T: Absorb<O>,
+{
+    handle: crate::WriteHandle<T, O>,
+    followers: Vec<Link>,
+    next: u64,
+    next_follower: u64,
+    max_backlog: usize,
+}
+
+impl<T, O> fmt::Debug for Leader<T, O>
+where
+    T: Absorb<O> + fmt::Debug,
+    O: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Leader")
+            .field("handle", &self.handle)
+            .field("followers", &self.followers.len())
+            .field("next", &self.next)
+            .field("max_backlog", &self.max_backlog)
+            .finish()
+    }
+}
+
+impl<T, O> Leader<T, O>
+where
+    T: Absorb<O>,
+    O: Serialize,
+{
+    /// Start leading with `handle`.
+    ///
+    /// Publishes any pending operations first, so that followers start out with them.
+    pub fn new(mut handle: crate::WriteHandle<T, O>) -> Self {
+        handle.publish();
+        Self {
+            handle,
+            followers: Vec::new(),
+            next: 0,
+            next_follower: 0,
+            max_backlog: MAX_BACKLOG,
+        }
+    }
+
+    /// Start sending to a new follower, beginning with a snapshot of the published data.
+    ///
+    /// The snapshot is serialized before this returns, and written to `stream` by the
+    /// follower's own thread, along with the batches that follow it.
+    pub fn add_follower<S>(&mut self, stream: S) -> io::Result<FollowerId>
+    where
+        T: Serialize,
+        S: Write + Send + 'static,
+    {
+        let mut greeting = Vec::new();
+        greeting.extend_from_slice(MAGIC);
+        greeting.extend_from_slice(&PROTOCOL.to_le_bytes());
+        greeting.push(SNAPSHOT);
+        {
+            let guard = self.handle.enter().expect("the handle has not been taken");
+            snapshot::write(&*guard, self.next, &mut greeting)?;
+        }
+        let id = FollowerId(self.next_follower);
+        self.followers
+            .push(Link::spawn(id, stream, greeting, self.max_backlog)?);
+        self.next_follower += 1;
+        Ok(id)
+    }
+
+    /// Publish the pending operations, and queue them for every follower as one batch.
+    ///
+    /// The batch is sent even if there is nothing pending, so that followers publish too.
+    /// Returns the followers that were disconnected, because their queue was full or their
+    /// stream failed. Fails, without publishing, only if the operations cannot be serialized.
+    pub fn publish(&mut self) -> io::Result<Vec<FollowerId>> {
+        let ops: Vec<&O> = self.handle.pending().collect();
+        let payload = bincode::serialize(&ops).map_err(invalid)?;
+        let size = u32::try_from(payload.len()).map_err(|_| invalid("batch too large"))?;
+        let mut frame = Vec::with_capacity(17 + payload.len());
+        frame.push(BATCH);
+        frame.extend_from_slice(&self.next.to_le_bytes());
+        frame.extend_from_slice(&size.to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        self.handle.publish();
+        self.next += 1;
+        let frame: Arc<[u8]> = frame.into();
+        let mut dropped = Vec::new();
+        self.followers.retain(|follower| {
+            // the queue is full, or its thread has stopped after a failed write.
+            let sent = follower.queue.try_send(Arc::clone(&frame)).is_ok();
+            if !sent {
+                dropped.push(follower.id);
+            }
+            sent
+        });
+        Ok(dropped)
+    }
+
+    /// Publish, but only if there are operations waiting to be published.
+    ///
+    /// Returns the followers that were disconnected, as [`publish`](Self::publish) does.
+    pub fn flush(&mut self) -> io::Result<Vec<FollowerId>> {
+        if self.handle.has_pending_operations() {
+            return self.publish();
+        }
+        Ok(Vec::new())
+    }
+}
+
+impl<T, O> Leader<T, O>
+where
+    T: Absorb<O>,
+{
+    /// The number of followers still connected, as of the last publish.
+    pub fn followers(&self) -> usize {
+        self.followers.len()
+    }
+
+    /// Set how many batches a follower added after this may have queued before it is
+    /// disconnected. The default is 1024.
+    ///
+    /// # Panics
+    ///
+    /// If `batches` is zero.
+    pub fn set_max_backlog(&mut self, batches: usize) {
+        assert!(batches > 0, "followers need room for at least one batch");
+        self.max_backlog = batches;
+    }
+
+    /// The number of batches sent so far.
+    pub fn position(&self) -> u64 {
+        self.next
+    }
+
+    /// Append an operation to be applied and sent at the next [`publish`](Self::publish).
+    pub fn append(&mut self, op: O) -> &mut Self {
+        self.handle.append(op);
+        self
+    }
+
+    /// Returns true if there are operations that have not yet been published.
+    pub fn has_pending_operations(&self) -> bool {
+        self.handle.has_pending_operations()
+    }
+
+    /// See [`crate::WriteHandle::rollback`].
+    pub fn rollback(&mut self) -> &mut Self {
+        self.handle.rollback();
+        self
+    }
+
+    /// See [`crate::WriteHandle::savepoint`].
+    pub fn savepoint(&self) -> Savepoint {
+        self.handle.savepoint()
+    }
+
+    /// See [`crate::WriteHandle::rollback_to`].
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> &mut Self {
+        self.handle.rollback_to(savepoint);
+        self
+    }
+
+    /// See [`crate::WriteHandle::transaction`].
+    pub fn transaction<F, R, E>(&mut self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Transaction<'_, T, O>) -> Result<R, E>,
+    {
+        self.handle.transaction(f)
+    }
+
+    /// Stop leading, disconnecting every follower once it has been sent what is already queued.
+    pub fn into_handle(self) -> crate::WriteHandle<T, O> {
+        self.handle
+    }
+}
+
+impl<T, O> ops::Deref for Leader<T, O>
+where
+    T: Absorb<O>,
+{
+    type Target = ReadHandle<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl<T, O> Extend<O> for Leader<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Append multiple operations to be applied and sent at the next
+    /// [`publish`](Self::publish).
+    fn extend<I>(&mut self, ops: I)
+    where
+        I: IntoIterator<Item = O>,
+    {
+        self.handle.extend(ops);
+    }
+}
+
+/// A write handle that applies the batches a [`Leader`] sends it.
+///
+/// Created with [`follow`].
+pub struct Follower<T, O, R>
+where
+    T: Absorb<O>,
+{
+    handle: crate::WriteHandle<T, O>,
+    stream: BufReader<R>,
+    next: u64,
+}
+
+impl<T, O, R> fmt::Debug for Follower<T, O, R>
+where
+    T: Absorb<O> + fmt::Debug,
+    O: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Follower")
+            .field("handle", &self.handle)
+            .field("next", &self.next)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Join the leader at the other end of `stream`.
+///
+/// Reads the leader's snapshot, and publishes it before returning the handles. Call
+/// [`Follower::apply_next`] or [`Follower::run`] to keep up with the leader after that.
+pub fn follow<T, O, R>(stream: R) -> io::Result<(Follower<T, O, R>, ReadHandle<T>)>
+where
+    T: Absorb<O> + Clone + DeserializeOwned,
+    O: DeserializeOwned,
+    R: Read,
+{
+    let mut stream = BufReader::new(stream);
+    let mut header = [0; 9];
+    stream.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a splitwrite leader"));
+    }
+    let protocol = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if protocol != PROTOCOL {
+        return Err(invalid(format!("unsupported protocol version {protocol}")));
+    }
+    if header[8] != SNAPSHOT {
+        return Err(invalid("expected a snapshot from the leader"));
+    }
+    let (t, next) = snapshot::read(&mut stream)?;
+    let (mut handle, r) = crate::new_from_empty(t);
+    handle.publish();
+    Ok((
+        Follower {
+            handle,
+            stream,
+            next,
+        },
+        r,
+    ))
+}
+
+impl<T, O, R> Follower<T, O, R>
+where
+    T: Absorb<O>,
+    O: DeserializeOwned,
+    R: Read,
+{
+    /// Wait for the next batch from the leader, and publish it.
+    ///
+    /// Returns `false` if the leader has closed the stream.
+    pub fn apply_next(&mut self) -> io::Result<bool> {
+        if self.stream.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let mut frame = [0; 17];
+        self.stream.read_exact(&mut frame)?;
+        if frame[0] != BATCH {
+            return Err(invalid(format!("unexpected message kind {}", frame[0])));
+        }
+        let number = u64::from_le_bytes(frame[1..9].try_into().unwrap());
+        if number != self.next {
+            return Err(invalid(format!(
+                "expected batch {}, but got batch {number}",
+                self.next
+            )));
+        }
+        let size = u32::from_le_bytes(frame[9..13].try_into().unwrap());
+        let checksum = u32::from_le_bytes(frame[13..].try_into().unwrap());
+        let mut payload = Vec::new();
+        (&mut self.stream)
+            .take(u64::from(size))
+            .read_to_end(&mut payload)?;
+        if payload.len() != size as usize {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if crc32fast::hash(&payload) != checksum {
+            return Err(invalid("batch checksum mismatch"));
+        }
+        let ops: Vec<O> = bincode::deserialize(&payload).map_err(invalid)?;
+
+        self.handle.extend(ops);
+        self.handle.publish();
+        self.next += 1;
+        Ok(true)
+    }
+
+    /// Apply batches until the leader closes the stream.
+    pub fn run(&mut self) -> io::Result<()> {
+        while self.apply_next()? {}
+        Ok(())
+    }
+}
+
+impl<T, O, R> Follower<T, O, R>
+where
+    T: Absorb<O>,
+{
+    /// The number of the next batch expected from the leader.
+    pub fn position(&self) -> u64 {
+        self.next
+    }
+
+    /// Stop following, for example to take over as the leader.
+    pub fn into_handle(self) -> crate::WriteHandle<T, O> {
+        self.handle
+    }
+}
+
+impl<T, O, R> ops::Deref for Follower<T, O, R>
+where
+    T: Absorb<O>,
+{
+    type Target = ReadHandle<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
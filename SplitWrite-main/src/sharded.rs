@@ -0,0 +1,297 @@
+//! Several independent left-right copies of `T`, for workloads with more writes than a single
+//! oplog keeps up with.
+//!
+//! A [`Sharded`] holds one [`WriteHandle`] per shard. Operations name the key they touch by
+//! implementing [`Keyed`], and a user-supplied router picks the shard for each key, typically
+//! from a hash of it. Readers get a [`ShardedReadHandle`] that shares the router, so
+//! [`ShardedReadHandle::enter_key`] finds the shard a key was written to.
+//!
+//! # Consistency
+//!
+//! Every shard is a left-right pair of its own, with all the usual guarantees: operations on a
+//! shard are absorbed in the order they were appended, and become visible to readers all at once
+//! when that shard publishes.
+//!
+//! There are no guarantees across shards. [`Sharded::publish`] publishes the shards one at a
+//! time, so a reader may see one shard's new state and another shard's old state, and
+//! operations sent to different shards may become visible in a different order than they were
+//! appended in. The guards from [`ShardedReadHandle::enter_all`] each pin their shard as of the
+//! moment it was entered, which is not the same moment for every shard. Keep anything that has
+//! to change atomically within a single shard.
+
+#[cfg(debug_assertions)]
+use crate::Savepoint;
+use crate::{Absorb, ReadGuard, ReadHandle, WriteHandle};
+use std::fmt;
+use std::sync::Arc;
+
+/// An operation that touches a single key, which decides the shard it is sent to.
+pub trait Keyed {
+    /// The type of the key.
+    type Key: ?Sized;
+
+    /// The key this operation touches.
+    fn key(&self) -> &Self::Key;
+}
+
+/// The writing half of a sharded left-right data structure.
+///
+/// Created with [`Sharded::new`] or [`Sharded::from_handles`]. See the
+/// [module documentation](self) for the consistency model.
+pub struct Sharded<T, O, R>
+where
+    T: Absorb<O>,
+{
+    shards: Vec<WriteHandle<T, O>>,
+    router: Arc<R>,
+    /// How far into each shard's pending operations they are known to follow the router.
+    #[cfg(debug_assertions)]
+    routed: Vec<Savepoint>,
+}
+
+impl<T, O, R> fmt::Debug for Sharded<T, O, R>
+where
+    T: Absorb<O> + fmt::Debug,
+    O: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sharded")
+            .field("shards", &self.shards)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The reading half of a sharded left-right data structure.
+pub struct ShardedReadHandle<T, R> {
+    shards: Vec<ReadHandle<T>>,
+    router: Arc<R>,
+}
+
+impl<T, R> fmt::Debug for ShardedReadHandle<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedReadHandle")
+            .field("shards", &self.shards)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, R> Clone for ShardedReadHandle<T, R> {
+    fn clone(&self) -> Self {
+        Self {
+            shards: self.shards.clone(),
+            router: Arc::clone(&self.router),
+        }
+    }
+}
+
+impl<T, O, R> Sharded<T, O, R>
+where
+    T: Absorb<O> + Default,
+{
+    /// Construct `shards` shards, each starting from the [`Default`] of `T`.
+    ///
+    /// `router` picks the shard for each key; its result is taken modulo the number of shards.
+    /// It is shared with every [`ShardedReadHandle`].
+    ///
+    /// # Panics
+    ///
+    /// If `shards` is zero.
+    pub fn new(shards: usize, router: R) -> (Self, ShardedReadHandle<T, R>) {
+        Self::from_handles((0..shards).map(|_| crate::new().0).collect(), router)
+    }
+}
+
+impl<T, O, R> Sharded<T, O, R>
+where
+    T: Absorb<O>,
+{
+    /// Construct from existing write handles, one per shard.
+    ///
+    /// # Panics
+    ///
+    /// If `shards` is empty.
+    pub fn from_handles(
+        shards: Vec<WriteHandle<T, O>>,
+        router: R,
+    ) -> (Self, ShardedReadHandle<T, R>) {
+        assert!(!shards.is_empty(), "there must be at least one shard");
+        let this = Self {
+            #[cfg(debug_assertions)]
+            routed: shards.iter().map(WriteHandle::savepoint).collect(),
+            shards,
+            router: Arc::new(router),
+        };
+        let r = this.reader();
+        (this, r)
+    }
+
+    /// The shard that operations on `key` are sent to.
+    pub fn shard_of<K>(&self, key: &K) -> usize
+    where
+        K: ?Sized,
+        R: Fn(&K) -> usize,
+    {
+        (self.router)(key) % self.shards.len()
+    }
+
+    /// Append an operation to the shard of its key, to be applied when that shard next
+    /// publishes.
+    pub fn append(&mut self, op: O) -> &mut Self
+    where
+        O: Keyed,
+        R: Fn(&O::Key) -> usize,
+    {
+        let shard = self.shard_of(op.key());
+        #[cfg(debug_assertions)]
+        self.check_routing(shard);
+        self.shards[shard].append(op);
+        #[cfg(debug_assertions)]
+        {
+            self.routed[shard] = self.shards[shard].savepoint();
+        }
+        self
+    }
+
+    /// Panic if an operation appended to `shard` through [`shard_mut`](Self::shard_mut) or
+    /// [`shards_mut`](Self::shards_mut) belongs to another shard.
+    #[cfg(debug_assertions)]
+    fn check_routing(&self, shard: usize)
+    where
+        O: Keyed,
+        R: Fn(&O::Key) -> usize,
+    {
+        let misrouted = self.shards[shard]
+            .pending_since(self.routed[shard])
+            .any(|op| self.shard_of(op.key()) != shard);
+        assert!(
+            !misrouted,
+            "an operation was appended to shard {shard} directly, but the router sends its key elsewhere"
+        );
+    }
+
+    /// Publish every shard, one after another.
+    ///
+    /// Readers may observe some shards published before others.
+    pub fn publish(&mut self) -> &mut Self {
+        for shard in &mut self.shards {
+            shard.publish();
+        }
+        self
+    }
+
+    /// Publish a single shard.
+    pub fn publish_shard(&mut self, shard: usize) -> &mut Self {
+        self.shards[shard].publish();
+        self
+    }
+
+    /// Returns true if any shard has operations that have not yet been published.
+    pub fn has_pending_operations(&self) -> bool {
+        self.shards.iter().any(WriteHandle::has_pending_operations)
+    }
+
+    /// The number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The write handle of a single shard.
+    ///
+    /// # Routing
+    ///
+    /// This handle does not go through the router. An operation appended to it must be one that
+    /// the router sends to `shard`, or readers looking its key up with
+    /// [`ShardedReadHandle::enter_key`] will never see it. In debug builds, [`append`](Self::append)
+    /// panics when it finds such an operation in the shard it appends to.
+    pub fn shard_mut(&mut self, shard: usize) -> &mut WriteHandle<T, O> {
+        &mut self.shards[shard]
+    }
+
+    /// The write handles of all shards, for example to publish them from several threads.
+    ///
+    /// # Routing
+    ///
+    /// As with [`shard_mut`](Self::shard_mut), these handles do not go through the router, and
+    /// each must only be given operations that the router sends to its shard.
+    pub fn shards_mut(&mut self) -> &mut [WriteHandle<T, O>] {
+        &mut self.shards
+    }
+
+    /// A new read handle to all shards.
+    pub fn reader(&self) -> ShardedReadHandle<T, R> {
+        ShardedReadHandle {
+            shards: self.shards.iter().map(|w| (**w).clone()).collect(),
+            router: Arc::clone(&self.router),
+        }
+    }
+
+    /// Take the write handles of all shards, in shard order.
+    pub fn into_handles(self) -> Vec<WriteHandle<T, O>> {
+        self.shards
+    }
+}
+
+impl<T, O, R> Extend<O> for Sharded<T, O, R>
+where
+    T: Absorb<O>,
+    O: Keyed,
+    R: Fn(&O::Key) -> usize,
+{
+    fn extend<I>(&mut self, ops: I)
+    where
+        I: IntoIterator<Item = O>,
+    {
+        for op in ops {
+            self.append(op);
+        }
+    }
+}
+
+impl<T, R> ShardedReadHandle<T, R> {
+    /// The number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard that operations on `key` are sent to, as picked by the writer's router.
+    pub fn shard_of<K>(&self, key: &K) -> usize
+    where
+        K: ?Sized,
+        R: Fn(&K) -> usize,
+    {
+        (self.router)(key) % self.shards.len()
+    }
+
+    /// Take a snapshot of the shard that holds `key`. See [`ReadHandle::enter`].
+    pub fn enter_key<K>(&self, key: &K) -> Option<ReadGuard<'_, T>>
+    where
+        K: ?Sized,
+        R: Fn(&K) -> usize,
+    {
+        self.enter(self.shard_of(key))
+    }
+
+    /// The read handle of a single shard.
+    pub fn shard(&self, shard: usize) -> &ReadHandle<T> {
+        &self.shards[shard]
+    }
+
+    /// Take a snapshot of a single shard. See [`ReadHandle::enter`].
+    pub fn enter(&self, shard: usize) -> Option<ReadGuard<'_, T>> {
+        self.shards[shard].enter()
+    }
+
+    /// Take a snapshot of every shard, in shard order.
+    ///
+    /// Each shard is entered separately, so the snapshots are not taken at the same moment,
+    /// and shards published in between are seen in their new state. Returns `None` if any
+    /// shard's writer has been dropped.
+    pub fn enter_all(&self) -> Option<Vec<ReadGuard<'_, T>>> {
+        self.shards.iter().map(ReadHandle::enter).collect()
+    }
+
+    /// Returns true if the writer of any shard has been dropped.
+    pub fn was_dropped(&self) -> bool {
+        self.shards.iter().any(ReadHandle::was_dropped)
+    }
+}
@@ -0,0 +1,589 @@
+//! Readers in other processes, with the `shm` feature.
+//!
+//! [`create`] puts both copies of a plain-old-data `T` in a memory-mapped file, and any process
+//! on the same host can then [`open`](ReadHandle::open) that file to read it without taking
+//! locks, just like a [`ReadHandle`](crate::ReadHandle) in the writer's own process. Since the
+//! file is mapped at a different address in every process, the published copy is recorded as an
+//! offset into the file rather than a pointer, and the reader epochs live in a fixed table of
+//! slots in the file rather than in the writer's memory.
+//!
+//! The file starts with a header, followed by the slot table and the two copies:
+//!
+//! - the header holds a magic number, the size and alignment of `T` and the number of slots,
+//!   which readers check when they open the file, and the offset of the published copy;
+//! - each slot holds a reader's epoch counter, and the id and start time of the process that
+//!   owns it;
+//! - each copy is aligned for `T`, and is written with a plain copy of the writer's data.
+//!
+//! The writer applies operations to a private copy of the data as they are appended, and a
+//! publish copies it into the file. A `T` is copied rather than replayed, so it should be
+//! cheap to copy.
+//!
+//! # Dead readers
+//!
+//! A reader process can die in the middle of a read, leaving its slot looking like it is still
+//! reading. On Unix, the writer checks whether the owner of a slot it is waiting for is still
+//! alive, and stops waiting for it if not, and new readers reuse the slots of dead ones.
+//! Elsewhere, a slot is only released when its [`ReadHandle`] is dropped.
+//!
+//! A process id can be reused by a new process once the old one has died. On Linux and Android,
+//! a slot also records when its owner started, so a new process with the old id is not taken
+//! for the owner. On other Unix systems only the id is compared, and a slot whose id has been
+//! reused stays taken until the new process exits.
+//!
+//! There must only ever be one writer for a file.
+
+use crate::{Absorb, WouldBlock};
+use memmap2::MmapRaw;
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::{fmt, io, mem, ops, ptr, thread};
+
+/// Types that can be shared with other processes by copying their bytes.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value of the type, and it must not hold pointers or
+/// references, which would point to nothing in other processes. `#[repr(C)]` structs of
+/// integers, floats and arrays of them qualify.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+pod!(
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    ()
+);
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+unsafe impl<T: Pod> Pod for crate::Applied<T> {}
+
+const MAGIC: u64 = u64::from_ne_bytes(*b"SWSHM\0\0\x01");
+
+#[repr(C)]
+struct Header {
+    /// Stored last when the file is created, so a reader that sees it sees the rest of the file.
+    magic: AtomicU64,
+    size: u64,
+    align: u64,
+    slots: u64,
+    /// The offset of the published copy from the start of the file.
+    active: AtomicU64,
+    /// Set once the writer has been dropped.
+    closed: AtomicU64,
+}
+
+/// A reader's epoch counter, padded to avoid false sharing between readers.
+#[repr(C, align(128))]
+struct Slot {
+    epoch: AtomicU64,
+    /// The [`process_id`] of the process holding the slot, or 0 if it is free.
+    owner: AtomicU64,
+}
+
+const SLOTS_AT: u64 = mem::size_of::<Slot>() as u64;
+const _: () = assert!(mem::size_of::<Header>() as u64 <= SLOTS_AT);
+
+/// The mapped file, and where the copies are in it.
+struct Shared {
+    map: MmapRaw,
+    slots: usize,
+    copies: [u64; 2],
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("slots", &self.slots)
+            .field("copies", &self.copies)
+            .finish()
+    }
+}
+
+fn round_up(n: u64, align: u64) -> u64 {
+    n.div_ceil(align) * align
+}
+
+/// The offsets of the two copies of `T`, and the length of the file.
+fn layout<T>(slots: usize) -> ([u64; 2], u64) {
+    let size = mem::size_of::<T>() as u64;
+    let align = mem::align_of::<T>().max(mem::align_of::<Slot>()) as u64;
+    let first = round_up(SLOTS_AT + SLOTS_AT * slots as u64, align);
+    let second = round_up(first + size, align);
+    ([first, second], second + size)
+}
+
+/// This process's id in the low half, and the low bits of its start time, if known, in the high
+/// half, so that a later process with the same id does not look like this one.
+fn process_id() -> u64 {
+    let pid = std::process::id();
+    u64::from(pid) | u64::from(start_time(pid).unwrap_or(0)) << 32
+}
+
+/// When process `pid` started, in clock ticks since boot, truncated to 32 bits.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn start_time(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the command name in parentheses may hold spaces; the start time is the 22nd field, and
+    // the 20th after the name.
+    let fields = &stat[stat.rfind(')')? + 1..];
+    let started: u64 = fields.split_whitespace().nth(19)?.parse().ok()?;
+    Some(started as u32)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn start_time(_: u32) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn is_alive(process: u64) -> bool {
+    let pid = process as u32;
+    let Ok(id) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    // Safety: signal 0 only checks that the process exists.
+    let exists = unsafe { libc::kill(id, 0) } == 0;
+    if !exists && io::Error::last_os_error().raw_os_error() != Some(libc::EPERM) {
+        return false;
+    }
+    // a process with this id exists, but it may not be the one that took the slot.
+    let started = (process >> 32) as u32;
+    started == 0 || start_time(pid).is_none_or(|now| now == started)
+}
+
+#[cfg(not(unix))]
+fn is_alive(_: u64) -> bool {
+    true
+}
+
+impl Shared {
+    fn map(file: &File, slots: usize, copies: [u64; 2]) -> io::Result<Self> {
+        Ok(Self {
+            map: MmapRaw::map_raw(file)?,
+            slots,
+            copies,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        // Safety: the mapping is page-aligned, and at least as long as the layout says.
+        unsafe { &*self.map.as_ptr().cast::<Header>() }
+    }
+
+    fn slot(&self, i: usize) -> &Slot {
+        assert!(i < self.slots);
+        // Safety: as for the header; the slots follow it at their own alignment.
+        unsafe {
+            &*self
+                .map
+                .as_ptr()
+                .add((SLOTS_AT * (i as u64 + 1)) as usize)
+                .cast()
+        }
+    }
+
+    fn copy<T>(&self, offset: u64) -> *mut T {
+        debug_assert!(self.copies.contains(&offset));
+        // Safety: both copies are within the mapping, and aligned for `T`.
+        unsafe { self.map.as_mut_ptr().add(offset as usize).cast() }
+    }
+
+    /// The offset of the published copy.
+    fn active(&self) -> u64 {
+        let active = self.header().active.load(Ordering::SeqCst);
+        // never trust the file with an offset to read a `T` from.
+        if active == self.copies[1] {
+            self.copies[1]
+        } else {
+            self.copies[0]
+        }
+    }
+
+    /// Claim a free slot, or the slot of a reader whose process has died.
+    fn claim(&self) -> io::Result<usize> {
+        let me = process_id();
+        for i in 0..self.slots {
+            let slot = self.slot(i);
+            let owner = slot.owner.load(Ordering::Acquire);
+            if owner != 0 && is_alive(owner) {
+                continue;
+            }
+            if slot
+                .owner
+                .compare_exchange(owner, me, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                if slot.epoch.load(Ordering::Acquire) % 2 == 1 {
+                    // the old owner died in the middle of a read.
+                    slot.epoch.fetch_add(1, Ordering::Release);
+                }
+                return Ok(i);
+            }
+        }
+        Err(io::Error::other("all reader slots are taken"))
+    }
+}
+
+/// The writing half of a left-right pair whose copies live in a memory-mapped file.
+///
+/// Created with [`create`].
+pub struct WriteHandle<T, O> {
+    shared: Arc<Shared>,
+    /// The data with every appended operation applied.
+    local: T,
+    pending: bool,
+    last_epochs: Vec<u64>,
+    _marker: PhantomData<fn(O)>,
+}
+
+impl<T, O> fmt::Debug for WriteHandle<T, O>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHandle")
+            .field("shared", &self.shared)
+            .field("local", &self.local)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+/// Create the file at `path` holding two copies of `initial`, with room for `readers` read
+/// handles at a time, and return a writer and a reader for it.
+///
+/// The returned reader takes up one of the slots. An existing file at `path` is overwritten.
+/// Other processes can read the data with [`ReadHandle::open`].
+pub fn create<T, O, P>(
+    path: P,
+    initial: T,
+    readers: usize,
+) -> io::Result<(WriteHandle<T, O>, ReadHandle<T>)>
+where
+    T: Absorb<O> + Pod,
+    P: AsRef<Path>,
+{
+    let (copies, len) = layout::<T>(readers);
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    // the file starts out zeroed, so every slot is free.
+    file.set_len(len)?;
+    let shared = Arc::new(Shared::map(&file, readers, copies)?);
+
+    // Safety: readers do not look past the magic number until it is stored, below.
+    unsafe {
+        let header = shared.map.as_mut_ptr().cast::<Header>();
+        (*header).size = mem::size_of::<T>() as u64;
+        (*header).align = mem::align_of::<T>() as u64;
+        (*header).slots = readers as u64;
+        (*header).active = AtomicU64::new(copies[0]);
+        ptr::write(shared.copy::<T>(copies[0]), initial);
+        ptr::write(shared.copy::<T>(copies[1]), initial);
+    }
+    shared.header().magic.store(MAGIC, Ordering::Release);
+
+    let w = WriteHandle {
+        shared,
+        local: initial,
+        pending: false,
+        last_epochs: vec![0; readers],
+        _marker: PhantomData,
+    };
+    let r = w.reader()?;
+    Ok((w, r))
+}
+
+impl<T, O> WriteHandle<T, O>
+where
+    T: Absorb<O> + Pod,
+{
+    /// Apply an operation to the writer's copy of the data, to be published at the next
+    /// [`publish`](Self::publish).
+    ///
+    /// The operation is absorbed once, with [`Absorb::absorb_second`], since the copies in the
+    /// file are updated by copying the result.
+    pub fn append(&mut self, op: O) -> &mut Self {
+        // Safety: the writer never modifies the published copy.
+        let published = unsafe { &*self.shared.copy::<T>(self.shared.active()) };
+        self.local.absorb_second(op, published);
+        self.pending = true;
+        self
+    }
+
+    /// Publish, waiting for readers to leave the copy that is written.
+    pub fn publish(&mut self) -> &mut Self {
+        while !self.readers_departed() {
+            thread::yield_now();
+        }
+        self.swap();
+        self
+    }
+
+    /// Publish, unless readers are still in the copy that would be written.
+    pub fn try_publish(&mut self) -> Result<&mut Self, WouldBlock> {
+        if !self.readers_departed() {
+            return Err(WouldBlock);
+        }
+        self.swap();
+        Ok(self)
+    }
+
+    /// Returns true if there are operations that have not yet been published.
+    pub fn has_pending_operations(&self) -> bool {
+        self.pending
+    }
+
+    /// The data as of the last publish, which is what readers currently see.
+    pub fn published(&self) -> &T {
+        // Safety: the writer never modifies the published copy.
+        unsafe { &*self.shared.copy::<T>(self.shared.active()) }
+    }
+
+    /// A new reader in this process.
+    pub fn reader(&self) -> io::Result<ReadHandle<T>> {
+        ReadHandle::new(Arc::clone(&self.shared))
+    }
+
+    fn readers_departed(&mut self) -> bool {
+        for (i, last) in self.last_epochs.iter_mut().enumerate() {
+            if *last % 2 == 0 {
+                continue;
+            }
+            let slot = self.shared.slot(i);
+            let now = slot.epoch.load(Ordering::Acquire);
+            if now != *last {
+                continue;
+            }
+            let owner = slot.owner.load(Ordering::Acquire);
+            if owner != 0 && !is_alive(owner) {
+                // the reader died in its read; whoever claims the slot next evens it out.
+                *last += 1;
+                continue;
+            }
+            return false;
+        }
+        true
+    }
+
+    fn swap(&mut self) {
+        let shared = &self.shared;
+        let active = shared.active();
+        let next = if active == shared.copies[0] {
+            shared.copies[1]
+        } else {
+            shared.copies[0]
+        };
+        // Safety: readers_departed says nobody is reading the copy that is not published.
+        unsafe { ptr::write(shared.copy::<T>(next), self.local) };
+        shared.header().active.store(next, Ordering::SeqCst);
+        for (i, last) in self.last_epochs.iter_mut().enumerate() {
+            *last = shared.slot(i).epoch.load(Ordering::SeqCst);
+        }
+        self.pending = false;
+    }
+}
+
+impl<T, O> Extend<O> for WriteHandle<T, O>
+where
+    T: Absorb<O> + Pod,
+{
+    /// Apply multiple operations, to be published at the next [`publish`](Self::publish).
+    fn extend<I>(&mut self, ops: I)
+    where
+        I: IntoIterator<Item = O>,
+    {
+        for op in ops {
+            self.append(op);
+        }
+    }
+}
+
+impl<T, O> Drop for WriteHandle<T, O> {
+    fn drop(&mut self) {
+        self.shared.header().closed.store(1, Ordering::Release);
+    }
+}
+
+/// A reader of data in a memory-mapped file, in this process or another.
+///
+/// Each handle holds one of the file's reader slots until it is dropped.
+pub struct ReadHandle<T> {
+    shared: Arc<Shared>,
+    slot: usize,
+    depth: Cell<usize>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for ReadHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHandle")
+            .field("shared", &self.shared)
+            .field("slot", &self.slot)
+            .finish()
+    }
+}
+
+impl<T> ReadHandle<T>
+where
+    T: Pod,
+{
+    /// Open a file made by [`create`] for reading.
+    ///
+    /// Fails if the file was not made by [`create`] for a type of the same size and alignment
+    /// as `T`, or if all of its reader slots are taken.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let invalid = |what| io::Error::new(io::ErrorKind::InvalidData, what);
+
+        let file_len = file.metadata()?.len();
+        if file_len < SLOTS_AT {
+            return Err(invalid("not a splitwrite shared memory file"));
+        }
+        let map = MmapRaw::map_raw(&file)?;
+        // Safety: the file is long enough to hold a header, and the mapping is page-aligned.
+        let header = unsafe { &*map.as_ptr().cast::<Header>() };
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(invalid("not a splitwrite shared memory file"));
+        }
+        // the writer never changes these once the magic number is stored.
+        if header.size != mem::size_of::<T>() as u64 || header.align != mem::align_of::<T>() as u64
+        {
+            return Err(invalid("the file holds a different type"));
+        }
+        let slots = usize::try_from(header.slots).map_err(|_| invalid("too many slots"))?;
+        let (copies, len) = layout::<T>(slots);
+        if file_len < len {
+            return Err(invalid("the file is too short"));
+        }
+        Self::new(Arc::new(Shared { map, slots, copies }))
+    }
+
+    fn new(shared: Arc<Shared>) -> io::Result<Self> {
+        let slot = shared.claim()?;
+        Ok(Self {
+            shared,
+            slot,
+            depth: Cell::new(0),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Another handle to the same file, with a slot of its own.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Self::new(Arc::clone(&self.shared))
+    }
+
+    /// Take a snapshot of the published data.
+    ///
+    /// Returns `None` if the writer has been dropped.
+    pub fn enter(&self) -> Option<ReadGuard<'_, T>> {
+        if self.was_dropped() {
+            return None;
+        }
+        let depth = self.depth.get();
+        if depth == 0 {
+            self.shared
+                .slot(self.slot)
+                .epoch
+                .fetch_add(1, Ordering::SeqCst);
+        }
+        self.depth.set(depth + 1);
+        // Safety: the writer does not write a copy while our epoch says we may be in it.
+        let t = unsafe { &*self.shared.copy::<T>(self.shared.active()) };
+        Some(ReadGuard { handle: self, t })
+    }
+
+    /// Returns true if the writer has been dropped.
+    pub fn was_dropped(&self) -> bool {
+        self.shared.header().closed.load(Ordering::Acquire) != 0
+    }
+}
+
+impl<T> Drop for ReadHandle<T> {
+    fn drop(&mut self) {
+        self.shared
+            .slot(self.slot)
+            .owner
+            .store(0, Ordering::Release);
+    }
+}
+
+/// A snapshot of the data in a memory-mapped file, which the writer will not change until the
+/// guard is dropped.
+pub struct ReadGuard<'rh, T> {
+    handle: &'rh ReadHandle<T>,
+    t: &'rh T,
+}
+
+impl<T: fmt::Debug> fmt::Debug for ReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadGuard").field("t", self.t).finish()
+    }
+}
+
+impl<T> ops::Deref for ReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.t
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        let depth = self.handle.depth.get() - 1;
+        self.handle.depth.set(depth);
+        if depth == 0 {
+            self.handle
+                .shared
+                .slot(self.handle.slot)
+                .epoch
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_alive, process_id};
+
+    #[cfg(unix)]
+    #[test]
+    fn owners_are_alive_while_running() {
+        assert!(is_alive(process_id()));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn reused_process_ids_are_not_alive() {
+        let me = process_id();
+        let started = (me >> 32) as u32;
+        assert_ne!(started, 0);
+        // the same id, but a process that started at another time.
+        let stale = (me & u64::from(u32::MAX)) | u64::from(started.wrapping_add(1).max(1)) << 32;
+        assert!(!is_alive(stale));
+    }
+}
@@ -0,0 +1,127 @@
+//! Serialized copies of the published data, with the `serde` feature.
+//!
+//! A snapshot is a small header, the data serialized with `bincode`, and a CRC-32 of both. The
+//! header holds a position, which [`ReadHandle::snapshot_to`] sets to the version of the data,
+//! and the write-ahead log sets to the number of records the snapshot includes.
+
+use crate::ReadHandle;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 4] = b"SWSN";
+const FORMAT: u32 = 1;
+
+impl<T> ReadHandle<T> {
+    /// Serialize the currently published data to `writer`, and return its version.
+    ///
+    /// The data is read under a [`ReadGuard`](crate::ReadGuard), so the writer can keep
+    /// appending in the meantime. Like any other guard, it holds up the publish after next
+    /// until the snapshot is written, or more publishes with
+    /// [`new_with_copies`](crate::new_with_copies).
+    ///
+    /// Restore the data with [`restore_from`](crate::restore_from).
+    pub fn snapshot_to<W>(&self, writer: W) -> io::Result<u64>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let guard = self
+            .enter()
+            .ok_or_else(|| io::Error::other("the writer has been dropped"))?;
+        let version = guard.version();
+        write(&*guard, version, writer)?;
+        Ok(version)
+    }
+}
+
+pub(crate) fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Passes everything through to `inner`, keeping a checksum of it.
+struct Checksummed<I> {
+    inner: I,
+    hasher: crc32fast::Hasher,
+}
+
+impl<I> Checksummed<I> {
+    fn new(inner: I) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Write a snapshot of `data` at `position`.
+pub(crate) fn write<T, W>(data: &T, position: u64, writer: W) -> io::Result<()>
+where
+    T: Serialize,
+    W: Write,
+{
+    let mut out = Checksummed::new(BufWriter::new(writer));
+    out.write_all(MAGIC)?;
+    out.write_all(&FORMAT.to_le_bytes())?;
+    out.write_all(&position.to_le_bytes())?;
+    bincode::serialize_into(&mut out, data).map_err(|e| match *e {
+        bincode::ErrorKind::Io(e) => e,
+        e => invalid(e),
+    })?;
+    let checksum = out.hasher.clone().finalize();
+    out.inner.write_all(&checksum.to_le_bytes())?;
+    out.inner.flush()
+}
+
+/// Read a snapshot, returning the data and its position.
+///
+/// Reads exactly the bytes of the snapshot, so `reader` can go on to hold other things.
+pub(crate) fn read<T, R>(reader: R) -> io::Result<(T, u64)>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    let mut input = Checksummed::new(reader);
+    let mut header = [0; 16];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a splitwrite snapshot"));
+    }
+    let format = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if format != FORMAT {
+        return Err(invalid(format!("unsupported snapshot format {format}")));
+    }
+    let position = u64::from_le_bytes(header[8..].try_into().unwrap());
+    let data = bincode::deserialize_from(&mut input).map_err(|e| match *e {
+        bincode::ErrorKind::Io(e) => e,
+        e => invalid(e),
+    })?;
+
+    let expected = input.hasher.finalize();
+    let mut checksum = [0; 4];
+    input.inner.read_exact(&mut checksum)?;
+    if u32::from_le_bytes(checksum) != expected {
+        return Err(invalid("snapshot checksum mismatch"));
+    }
+    Ok((data, position))
+}
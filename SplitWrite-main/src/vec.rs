@@ -0,0 +1,320 @@
+//! A concurrent vector with index-based operations.
+//!
+//! Every element is stored once and aliased between the two copies of the vector with
+//! [`Aliased`], so large elements are not duplicated and need not implement [`Clone`]. Readers
+//! see the published vector as a slice.
+//!
+//! ```
+//! let (mut w, r) = splitwrite::vec::new();
+//!
+//! w.push(1).push(2).push(3);
+//! w.publish();
+//! assert_eq!(&*r.enter().unwrap(), &[1, 2, 3]);
+//!
+//! w.set(0, 4).swap_remove(1);
+//! w.publish();
+//! assert_eq!(&*r.enter().unwrap(), &[4, 3]);
+//! ```
+use crate::aliasing::{Aliased, DoDrop, NoDrop};
+use crate::handles::{read_handle, write_handle};
+use crate::{Absorb, ReadGuard};
+use std::fmt;
+use std::ops::Deref;
+
+/// A vector as seen through a [`ReadHandle`], which readers usually get at as a slice.
+///
+/// Every copy of the data has its own list of the elements, in the same order, but each element
+/// is stored once and shared between all of the lists.
+pub struct SplitVec<T> {
+    data: Vec<Aliased<T, NoDrop>>,
+}
+
+impl<T> Default for SplitVec<T> {
+    fn default() -> Self {
+        Self { data: Vec::new() }
+    }
+}
+
+impl<T> fmt::Debug for SplitVec<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> SplitVec<T> {
+    /// Returns the elements of the vector as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        // safety: Aliased<T, _> is repr(transparent) over MaybeUninit<T>, which has the same
+        // layout as T, and every element in the vector is initialized.
+        unsafe { std::slice::from_raw_parts(self.data.as_ptr().cast::<T>(), self.data.len()) }
+    }
+}
+
+impl<T> Deref for SplitVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<T> AsRef<[T]> for SplitVec<T> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+pub(crate) enum Operation<T> {
+    Push(Aliased<T, NoDrop>),
+    Pop,
+    Set(usize, Aliased<T, NoDrop>),
+    SwapRemove(usize),
+    Truncate(usize),
+    Extend(Vec<Aliased<T, NoDrop>>),
+}
+
+impl<T> fmt::Debug for Operation<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Push(v) => f.debug_tuple("Push").field(v).finish(),
+            Operation::Pop => f.write_str("Pop"),
+            Operation::Set(i, v) => f.debug_tuple("Set").field(i).field(v).finish(),
+            Operation::SwapRemove(i) => f.debug_tuple("SwapRemove").field(i).finish(),
+            Operation::Truncate(len) => f.debug_tuple("Truncate").field(len).finish(),
+            Operation::Extend(vs) => f.debug_tuple("Extend").field(vs).finish(),
+        }
+    }
+}
+
+impl<T> Absorb<Operation<T>> for SplitVec<T> {
+    fn absorb_first(&mut self, operation: &mut Operation<T>, _: &Self) {
+        // pushed, set and extended elements stay owned by the operation until the last copy
+        // absorbs it, and an element popped, overwritten, swap-removed or truncated away here is
+        // still in the lists that have yet to see the operation, so nothing is dropped here.
+        match operation {
+            Operation::Push(value) => {
+                self.data.push(unsafe { value.alias() });
+            }
+            Operation::Pop => {
+                self.data.pop();
+            }
+            Operation::Set(index, value) => {
+                self.data[*index] = unsafe { value.alias() };
+            }
+            Operation::SwapRemove(index) => {
+                self.data.swap_remove(*index);
+            }
+            Operation::Truncate(len) => {
+                self.data.truncate(*len);
+            }
+            Operation::Extend(values) => {
+                self.data
+                    .extend(values.iter().map(|v| unsafe { v.alias() }));
+            }
+        }
+    }
+
+    fn absorb_second(&mut self, operation: Operation<T>, _: &Self) {
+        // safety: every other list has already absorbed the operation, so an element that is
+        // popped, overwritten by a set, swap-removed or truncated away here is now in none of
+        // them. the writer has also waited for readers of this list to leave, so no one can
+        // still be looking at the element when it is dropped here.
+        match operation {
+            Operation::Push(value) => {
+                self.data.push(value);
+            }
+            Operation::Pop => {
+                if let Some(old) = self.data.pop() {
+                    drop(unsafe { old.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Set(index, value) => {
+                let old = std::mem::replace(&mut self.data[index], value);
+                drop(unsafe { old.change_drop::<DoDrop>() });
+            }
+            Operation::SwapRemove(index) => {
+                let old = self.data.swap_remove(index);
+                drop(unsafe { old.change_drop::<DoDrop>() });
+            }
+            Operation::Truncate(len) => {
+                if len < self.data.len() {
+                    for old in self.data.drain(len..) {
+                        drop(unsafe { old.change_drop::<DoDrop>() });
+                    }
+                }
+            }
+            Operation::Extend(values) => {
+                self.data.extend(values);
+            }
+        }
+    }
+
+    fn drop_op(operation: Operation<T>) {
+        // the operation was never absorbed, so it holds the only aliases of its elements.
+        match operation {
+            Operation::Push(value) | Operation::Set(_, value) => {
+                drop(unsafe { value.change_drop::<DoDrop>() });
+            }
+            Operation::Extend(values) => {
+                for value in values {
+                    drop(unsafe { value.change_drop::<DoDrop>() });
+                }
+            }
+            Operation::Pop | Operation::SwapRemove(_) | Operation::Truncate(_) => {}
+        }
+    }
+
+    fn drop_second(self: Box<Self>) {
+        let mut this = self;
+        for value in this.data.drain(..) {
+            drop(unsafe { value.change_drop::<DoDrop>() });
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        assert_eq!(self.data.len(), 0);
+        self.data
+            .extend(first.data.iter().map(|v| unsafe { v.alias() }));
+    }
+}
+
+read_handle! {
+    /// A handle that may be used to read from a [`SplitVec`].
+    ///
+    /// Like [`crate::ReadHandle`], each thread should use its own handle; clone one per thread.
+    pub struct ReadHandle<T>(SplitVec<T>);
+}
+
+impl<T> ReadHandle<T> {
+    /// Take a snapshot of the published vector as a slice.
+    ///
+    /// Returns `None` if the [`WriteHandle`] has been dropped.
+    pub fn enter(&self) -> Option<ReadGuard<'_, [T]>> {
+        Some(ReadGuard::map(self.handle.enter()?, SplitVec::as_slice))
+    }
+
+    /// Returns a guarded reference to the published element at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<ReadGuard<'_, T>> {
+        ReadGuard::try_map(self.enter()?, |s| s.get(index))
+    }
+
+    /// Returns the number of published elements.
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |s| s.len())
+    }
+
+    /// Returns true if the published vector has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.enter().is_none_or(|s| s.is_empty())
+    }
+}
+
+write_handle! {
+    /// A handle that may be used to modify a [`SplitVec`].
+    ///
+    /// Modifications are only visible to readers after [`publish`](Self::publish). The handle
+    /// tracks the length the vector will have once all pending operations are published, and
+    /// index-based operations panic if the index is out of bounds for that length, just like the
+    /// corresponding [`Vec`] methods. The handle dereferences to a [`ReadHandle`] that sees the
+    /// published vector.
+    pub struct WriteHandle<T>(SplitVec<T>, Operation<T>) {
+        len: usize = 0,
+    }
+    on_rollback(|this| {
+        // with nothing pending, the published vector is what we will end up with.
+        this.len = this.handle.enter().map_or(0, |v| v.len());
+    })
+}
+
+impl<T> WriteHandle<T> {
+    /// Append `value` to the end of the vector.
+    pub fn push(&mut self, value: T) -> &mut Self {
+        self.handle.append(Operation::Push(Aliased::from(value)));
+        self.len += 1;
+        self
+    }
+
+    /// Remove the last element of the vector, if any.
+    pub fn pop(&mut self) -> &mut Self {
+        if self.len != 0 {
+            self.handle.append(Operation::Pop);
+            self.len -= 1;
+        }
+        self
+    }
+
+    /// Replace the element at `index` with `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) -> &mut Self {
+        self.check_index(index);
+        self.handle
+            .append(Operation::Set(index, Aliased::from(value)));
+        self
+    }
+
+    /// Remove the element at `index`, replacing it with the last element of the vector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn swap_remove(&mut self, index: usize) -> &mut Self {
+        self.check_index(index);
+        self.handle.append(Operation::SwapRemove(index));
+        self.len -= 1;
+        self
+    }
+
+    /// Shorten the vector to `len` elements, dropping the rest.
+    ///
+    /// Has no effect if the vector is already shorter than `len`.
+    pub fn truncate(&mut self, len: usize) -> &mut Self {
+        if len < self.len {
+            self.handle.append(Operation::Truncate(len));
+            self.len = len;
+        }
+        self
+    }
+
+    /// Returns the number of elements the vector will have once all pending operations are
+    /// published.
+    pub fn pending_len(&self) -> usize {
+        self.len
+    }
+
+    fn check_index(&self, index: usize) {
+        assert!(
+            index < self.len,
+            "index out of bounds: the len is {} but the index is {}",
+            self.len,
+            index
+        );
+    }
+}
+
+impl<T> Extend<T> for WriteHandle<T> {
+    /// Append every element of `iter` to the end of the vector.
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let values: Vec<_> = iter.into_iter().map(Aliased::from).collect();
+        if !values.is_empty() {
+            self.len += values.len();
+            self.handle.append(Operation::Extend(values));
+        }
+    }
+}
+
+/// Create an empty vector, returning its write handle and a read handle.
+pub fn new<T>() -> (WriteHandle<T>, ReadHandle<T>) {
+    let (w, r) = crate::new();
+    WriteHandle::from_handles(w, r)
+}
@@ -0,0 +1,39 @@
+use crate::sync::CachePadded;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, Thread};
+
+/// Lets readers wake a writer that has parked while waiting for them to leave a copy.
+///
+/// Readers only ever load `waiting`, so this costs them nothing unless the writer is parked.
+#[derive(Debug, Default)]
+pub(crate) struct Wakeup {
+    /// Loaded by every reader as it leaves, so kept away from anything that is written to.
+    waiting: CachePadded<AtomicBool>,
+    writer: Mutex<Option<Thread>>,
+}
+
+impl Wakeup {
+    /// Announce that the current thread is about to park until a reader departs.
+    ///
+    /// The writer must check the epochs again after this and before parking, since a reader
+    /// that departed before the announcement will not wake it.
+    pub(crate) fn arm(&self) {
+        *self.writer.lock().unwrap() = Some(thread::current());
+        self.waiting.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop readers from waking the writer.
+    pub(crate) fn disarm(&self) {
+        self.waiting.store(false, Ordering::Relaxed);
+    }
+
+    /// Wake the writer if it is parked. Called by readers whenever they leave an epoch.
+    pub(crate) fn notify(&self) {
+        if self.waiting.load(Ordering::SeqCst) {
+            if let Some(writer) = &*self.writer.lock().unwrap() {
+                writer.unpark();
+            }
+        }
+    }
+}
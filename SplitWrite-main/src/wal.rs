@@ -0,0 +1,611 @@
+//! Durability for the operation log, with the `wal` feature.
+//!
+//! A [`WriteHandle`] from this module writes every operation to an append-only log file as it
+//! is appended, and [`open`] rebuilds the data from that log after a restart.
+//! Operations are serialized with `serde`, so `O` must implement `Serialize` and
+//! `DeserializeOwned`.
+//!
+//! # What is logged
+//!
+//! [`append`](WriteHandle::append) and [`extend`](WriteHandle::extend) log their operations
+//! before they join the pending ones, and publishes and rollbacks are logged as markers after
+//! the operations they cover, so the log always matches the handle. Operations appended in a
+//! [`transaction`](WriteHandle::transaction) are logged together when it commits.
+//!
+//! Recovery replays the log in order. Operations followed by a publish are absorbed into the
+//! data that is published on [`open`], rolled back ones are dropped, and those appended after the
+//! last publish are pending again on the new handle, to be published or rolled back as if the
+//! restart never happened. Dropping the handle publishes its pending operations, as it does for
+//! [`crate::WriteHandle`], so that publish is logged first.
+//!
+//! Each record is its length and a CRC-32 of its contents, followed by a byte giving its kind and,
+//! for appends, the operations. A record that was only partly written when the process died
+//! fails its checksum, and is cut off the end of the log on the next [`open`], along with
+//! anything after it.
+//!
+//! How soon a record is on disk, rather than in the operating system's cache, depends on the
+//! [`SyncPolicy`].
+//!
+//! # Checkpoints
+//!
+//! Left alone, the log grows with every operation, and so does the time it takes to replay.
+//! [`WriteHandle::checkpoint`] writes a snapshot of the published data next to the log, at the
+//! log's path with `.snapshot` appended, and then empties the log. [`restore`] loads that
+//! snapshot and replays only the records logged after it. Both files are replaced by renaming
+//! a finished file over them, so a crash partway through a checkpoint leaves either the old or
+//! the new snapshot, and a log that [`restore`] knows how much of to skip.
+
+use crate::snapshot::{self, invalid};
+use crate::{Absorb, ReadHandle, Savepoint, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fmt, ops};
+
+const MAGIC: &[u8; 4] = b"SWAL";
+const FORMAT: u32 = 3;
+const HEADER: u64 = 16;
+
+const APPEND: u8 = 0;
+const PUBLISH: u8 = 1;
+const ROLLBACK: u8 = 2;
+
+/// When the log is flushed to disk with `fsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// After every record, before the call that wrote it returns. Nothing appended is lost in
+    /// a crash.
+    #[default]
+    Always,
+    /// After a record if at least this long has passed since the last sync.
+    ///
+    /// Syncing only happens when a record is written, not on a timer. While the writer keeps
+    /// logging, a crash loses at most the records of the last interval, but records written
+    /// just before it goes quiet stay unsynced until the next record or
+    /// [`WriteHandle::sync`], however long that takes. Call `sync` before going idle to bound
+    /// what a crash can lose.
+    Every(Duration),
+    /// Only on [`WriteHandle::sync`], leaving the rest to the operating system.
+    Never,
+}
+
+/// A [`crate::WriteHandle`] that logs operations to disk as they are appended.
+///
+/// Created with [`open`]. Everything that changes the pending operations is logged first, and
+/// fails without changing them if the log cannot be written.
+pub struct WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    handle: crate::WriteHandle<T, O>,
+    log: Log,
+}
+
+impl<T, O> fmt::Debug for WriteHandle<T, O>
+where
+    T: Absorb<O> + fmt::Debug,
+    O: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHandle")
+            .field("handle", &self.handle)
+            .field("log", &self.log)
+            .finish()
+    }
+}
+
+/// Open the log at `path`, creating it if it does not exist, and rebuild the data from it.
+///
+/// The data starts out as the [`Default`] of `T`, and every published operation in the log is
+/// absorbed into it with [`Absorb::absorb_second`], in order. The result is published before the
+/// handles are returned, so readers start out seeing the recovered data, and operations that
+/// were still pending are pending again on the returned handle.
+///
+/// Fails if the file is not a log, or holds operations that do not deserialize as `O`. A record
+/// at the end that was only partly written is removed from the file. Logs that have been
+/// [checkpointed](WriteHandle::checkpoint) must be opened with [`restore`] instead.
+pub fn open<T, O, P>(path: P, sync: SyncPolicy) -> io::Result<(WriteHandle<T, O>, ReadHandle<T>)>
+where
+    T: Absorb<O> + Default,
+    O: Serialize + DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let (mut handle, r) = crate::new();
+    let (log, pending) = replay(path.as_ref(), sync, &mut handle, 0)?;
+    if log.start != 0 {
+        return Err(invalid(
+            "the log has been checkpointed; open it with wal::restore",
+        ));
+    }
+    Ok((WriteHandle::recovered(handle, log, pending), r))
+}
+
+/// Like [`open`], but starting from the snapshot of the last
+/// [`checkpoint`](WriteHandle::checkpoint), if there is one.
+///
+/// Only the records logged after the snapshot are replayed on top of it.
+pub fn restore<T, O, P>(path: P, sync: SyncPolicy) -> io::Result<(WriteHandle<T, O>, ReadHandle<T>)>
+where
+    T: Absorb<O> + Default + Clone + DeserializeOwned,
+    O: Serialize + DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let ((mut handle, r), position) = match File::open(snapshot_path(path)) {
+        Ok(file) => {
+            let (t, position) = snapshot::read(BufReader::new(file))?;
+            (crate::new_from_empty(t), position)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (crate::new(), 0),
+        Err(e) => return Err(e),
+    };
+    let (mut log, pending) = replay(path, sync, &mut handle, position)?;
+    if log.start > position {
+        return Err(invalid("the log starts after the snapshot"));
+    }
+    if log.next < position {
+        // records the snapshot includes were lost from the log; number new ones after it.
+        log.reset(position, &pending)?;
+    }
+    Ok((WriteHandle::recovered(handle, log, pending), r))
+}
+
+/// Open the log, and absorb the operations published from record `position` on into `handle`,
+/// which must not have published yet.
+///
+/// Returns the log and the operations that were still pending at its end.
+fn replay<T, O>(
+    path: &Path,
+    sync: SyncPolicy,
+    handle: &mut crate::WriteHandle<T, O>,
+    position: u64,
+) -> io::Result<(Log, Vec<O>)>
+where
+    T: Absorb<O>,
+    O: DeserializeOwned,
+{
+    let mut pending = Vec::new();
+    let log = Log::open(path, sync, |number, record| match record {
+        Record::Append(ops) => pending.extend(ops),
+        Record::Rollback(kept) => {
+            let kept = kept.min(pending.len());
+            pending.drain(kept..).for_each(T::drop_op);
+        }
+        // the handle has not published yet, so these go straight through absorb_second.
+        Record::Publish if number >= position => handle.extend(pending.drain(..)),
+        // already part of the snapshot.
+        Record::Publish => pending.drain(..).for_each(T::drop_op),
+    })?;
+    Ok((log, pending))
+}
+
+fn snapshot_path(log: &Path) -> PathBuf {
+    let mut path = OsString::from(log);
+    path.push(".snapshot");
+    path.into()
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(".tmp");
+    path.into()
+}
+
+/// Write a file by renaming a finished one over it.
+fn replace<F>(path: &Path, write: F) -> io::Result<File>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let temporary = temporary_path(path);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temporary)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(file)
+}
+
+impl<T, O> WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Publish what was recovered, and make the operations that were pending when the log
+    /// ended pending again.
+    fn recovered(mut handle: crate::WriteHandle<T, O>, log: Log, pending: Vec<O>) -> Self {
+        handle.publish();
+        handle.extend(pending);
+        Self { handle, log }
+    }
+
+    /// Log a publish, then publish the pending operations.
+    ///
+    /// If writing the log fails, nothing is published, and the operations stay pending.
+    pub fn publish(&mut self) -> io::Result<&mut Self> {
+        if self.handle.has_pending_operations() {
+            self.log.publish()?;
+        }
+        self.handle.publish();
+        Ok(self)
+    }
+
+    /// Publish, but only if there are operations waiting to be published.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.handle.has_pending_operations() {
+            self.publish()?;
+        }
+        Ok(())
+    }
+
+    /// Flush everything logged so far to disk, regardless of the [`SyncPolicy`].
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.log.sync()
+    }
+
+    /// Returns true if there are operations that have not yet been published.
+    pub fn has_pending_operations(&self) -> bool {
+        self.handle.has_pending_operations()
+    }
+
+    /// Log and discard every operation appended since the last publish.
+    ///
+    /// See [`crate::WriteHandle::rollback`].
+    pub fn rollback(&mut self) -> io::Result<&mut Self> {
+        if self.handle.has_pending_operations() {
+            self.log.rollback(0)?;
+            self.handle.rollback();
+        }
+        Ok(self)
+    }
+
+    /// See [`crate::WriteHandle::savepoint`].
+    pub fn savepoint(&self) -> Savepoint {
+        self.handle.savepoint()
+    }
+
+    /// Log and discard the operations appended since `savepoint` was taken.
+    ///
+    /// See [`crate::WriteHandle::rollback_to`], including for when this panics.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> io::Result<&mut Self> {
+        let kept = self.handle.kept_by(savepoint);
+        if self.handle.pending().count() > kept {
+            self.log.rollback(kept)?;
+            self.handle.rollback_to(savepoint);
+        }
+        Ok(self)
+    }
+}
+
+impl<T, O> WriteHandle<T, O>
+where
+    T: Absorb<O>,
+    O: Serialize,
+{
+    /// Log an operation, then append it to be applied at the next [`publish`](Self::publish).
+    ///
+    /// If writing the log fails, the operation is dropped without being appended.
+    pub fn append(&mut self, op: O) -> io::Result<&mut Self> {
+        self.extend(std::iter::once(op))
+    }
+
+    /// Log multiple operations as one record, then append them to be applied at the next
+    /// [`publish`](Self::publish).
+    ///
+    /// If writing the log fails, none of the operations are appended.
+    pub fn extend<I>(&mut self, ops: I) -> io::Result<&mut Self>
+    where
+        I: IntoIterator<Item = O>,
+    {
+        let ops: Vec<O> = ops.into_iter().collect();
+        if ops.is_empty() {
+            return Ok(self);
+        }
+        if let Err(e) = self.log.append(&ops) {
+            ops.into_iter().for_each(T::drop_op);
+            return Err(e);
+        }
+        self.handle.extend(ops);
+        Ok(self)
+    }
+
+    /// Run `f` as a [`crate::WriteHandle::transaction`], and log the operations it appended if
+    /// it commits.
+    ///
+    /// If writing the log fails, the transaction is rolled back, and the error is returned in
+    /// place of the result of `f`.
+    pub fn transaction<F, R, E>(&mut self, f: F) -> io::Result<Result<R, E>>
+    where
+        F: FnOnce(&mut Transaction<'_, T, O>) -> Result<R, E>,
+    {
+        let savepoint = self.handle.savepoint();
+        let kept = self.handle.kept_by(savepoint);
+        let result = self.handle.transaction(f);
+        if result.is_ok() && self.handle.pending().count() > kept {
+            if let Err(e) = self.log.append(self.handle.pending().skip(kept)) {
+                self.handle.rollback_to(savepoint);
+                return Err(e);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Write a snapshot of the published data, then empty the log.
+    ///
+    /// Pending operations are not part of the snapshot, and are logged again at the start of the
+    /// emptied log. Reopen a checkpointed log with [`restore`].
+    pub fn checkpoint(&mut self) -> io::Result<()>
+    where
+        T: Serialize,
+    {
+        // the snapshot must not get ahead of what is on disk in the log.
+        self.log.sync()?;
+        let position = self.log.next;
+        {
+            let guard = self.handle.enter().expect("the handle has not been taken");
+            replace(&snapshot_path(&self.log.path), |file| {
+                snapshot::write(&*guard, position, file)
+            })?;
+        }
+        let pending: Vec<&O> = self.handle.pending().collect();
+        self.log.reset(position, pending)
+    }
+}
+
+impl<T, O> Drop for WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    fn drop(&mut self) {
+        // dropping the inner handle publishes the pending operations, so the log has to say so,
+        // or they must not be published at all.
+        if self.handle.has_pending_operations() && self.log.publish().is_err() {
+            self.handle.rollback();
+        }
+    }
+}
+
+impl<T, O> ops::Deref for WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    type Target = ReadHandle<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+/// A record read back from the log.
+pub(crate) enum Record<O> {
+    /// Operations appended together.
+    Append(Vec<O>),
+    /// A publish of every operation appended before it.
+    Publish,
+    /// A rollback that kept this many of the pending operations.
+    Rollback(usize),
+}
+
+/// The log file, positioned at the end of its last complete record.
+#[derive(Debug)]
+pub(crate) struct Log {
+    file: File,
+    path: PathBuf,
+    len: u64,
+    /// The number of the first record in the file.
+    start: u64,
+    /// The number of the next record to be written.
+    next: u64,
+    sync: SyncPolicy,
+    last_sync: Instant,
+}
+
+fn write_header(file: &mut File, start: u64) -> io::Result<()> {
+    file.write_all(MAGIC)?;
+    file.write_all(&FORMAT.to_le_bytes())?;
+    file.write_all(&start.to_le_bytes())
+}
+
+/// Frame `payload` with its length and checksum.
+fn record(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let size = u32::try_from(payload.len()).map_err(|_| invalid("record too large"))?;
+    let mut record = Vec::with_capacity(8 + payload.len());
+    record.extend_from_slice(&size.to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    Ok(record)
+}
+
+fn append_payload<'a, O, I>(ops: I) -> io::Result<Vec<u8>>
+where
+    O: Serialize + 'a,
+    I: IntoIterator<Item = &'a O>,
+{
+    let ops: Vec<&O> = ops.into_iter().collect();
+    let mut payload = vec![APPEND];
+    bincode::serialize_into(&mut payload, &ops).map_err(invalid)?;
+    Ok(payload)
+}
+
+fn parse<O>(payload: &[u8]) -> io::Result<Record<O>>
+where
+    O: DeserializeOwned,
+{
+    match payload.split_first() {
+        Some((&APPEND, ops)) => Ok(Record::Append(bincode::deserialize(ops).map_err(invalid)?)),
+        Some((&PUBLISH, [])) => Ok(Record::Publish),
+        Some((&ROLLBACK, kept)) => {
+            let kept = kept
+                .try_into()
+                .map_err(|_| invalid("malformed rollback record"))?;
+            Ok(Record::Rollback(u64::from_le_bytes(kept) as usize))
+        }
+        _ => Err(invalid("unknown log record")),
+    }
+}
+
+impl Log {
+    /// Open or create the log, passing the number and contents of every complete record to
+    /// `replay`.
+    pub(crate) fn open<O, F>(path: &Path, sync: SyncPolicy, mut replay: F) -> io::Result<Self>
+    where
+        O: DeserializeOwned,
+        F: FnMut(u64, Record<O>),
+    {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut this = Self {
+            file: file.try_clone()?,
+            path: path.to_owned(),
+            len: HEADER,
+            start: 0,
+            next: 0,
+            sync,
+            last_sync: Instant::now(),
+        };
+        let file_len = file.metadata()?.len();
+        if file_len == 0 {
+            write_header(&mut file, 0)?;
+            file.sync_all()?;
+            return Ok(this);
+        }
+
+        let mut reader = BufReader::new(&mut file);
+        let mut header = [0; HEADER as usize];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| invalid("not a splitwrite log"))?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a splitwrite log"));
+        }
+        let format = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if format != FORMAT {
+            return Err(invalid(format!("unsupported log format {format}")));
+        }
+        this.start = u64::from_le_bytes(header[8..].try_into().unwrap());
+        this.next = this.start;
+
+        let mut payload = Vec::new();
+        loop {
+            let mut frame = [0; 8];
+            if reader.read_exact(&mut frame).is_err() {
+                break;
+            }
+            let size = u64::from(u32::from_le_bytes(frame[..4].try_into().unwrap()));
+            let checksum = u32::from_le_bytes(frame[4..].try_into().unwrap());
+            if size > file_len - this.len - 8 {
+                break;
+            }
+            payload.resize(size as usize, 0);
+            if reader.read_exact(&mut payload).is_err() || crc32fast::hash(&payload) != checksum {
+                break;
+            }
+            replay(this.next, parse(&payload)?);
+            this.len += 8 + size;
+            this.next += 1;
+        }
+        drop(reader);
+
+        if this.len < file_len {
+            // a torn write at the end; drop it so new records follow the last good one.
+            file.set_len(this.len)?;
+            file.sync_all()?;
+        }
+        this.file.seek(SeekFrom::Start(this.len))?;
+        Ok(this)
+    }
+
+    /// Write one record holding `ops`.
+    pub(crate) fn append<'a, O, I>(&mut self, ops: I) -> io::Result<()>
+    where
+        O: Serialize + 'a,
+        I: IntoIterator<Item = &'a O>,
+    {
+        self.write(&append_payload(ops)?)
+    }
+
+    /// Write a record saying that everything appended so far was published.
+    pub(crate) fn publish(&mut self) -> io::Result<()> {
+        self.write(&[PUBLISH])
+    }
+
+    /// Write a record saying that all but the first `kept` pending operations were rolled back.
+    pub(crate) fn rollback(&mut self, kept: usize) -> io::Result<()> {
+        let mut payload = vec![ROLLBACK];
+        payload.extend_from_slice(&(kept as u64).to_le_bytes());
+        self.write(&payload)
+    }
+
+    /// Write one record, and sync it according to the policy.
+    fn write(&mut self, payload: &[u8]) -> io::Result<()> {
+        let record = record(payload)?;
+        let written = self.file.write_all(&record).and_then(|_| match self.sync {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::Every(interval) if self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        });
+        if let Err(e) = written {
+            // the handle is left as it was, so the log must be too.
+            let _ = self
+                .file
+                .set_len(self.len)
+                .and_then(|_| self.file.seek(SeekFrom::Start(self.len)));
+            return Err(e);
+        }
+        self.len += record.len() as u64;
+        self.next += 1;
+        Ok(())
+    }
+
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Replace the log with one whose first record will be number `start`, and that holds
+    /// only `pending`, the operations appended since the last publish.
+    pub(crate) fn reset<'a, O, I>(&mut self, start: u64, pending: I) -> io::Result<()>
+    where
+        O: Serialize + 'a,
+        I: IntoIterator<Item = &'a O>,
+    {
+        let mut pending = pending.into_iter().peekable();
+        let record = match pending.peek() {
+            Some(_) => Some(record(&append_payload(pending)?)?),
+            None => None,
+        };
+        let mut file = replace(&self.path, |file| {
+            write_header(file, start)?;
+            record
+                .as_ref()
+                .map_or(Ok(()), |record| file.write_all(record))
+        })?;
+        let records = u64::from(record.is_some());
+        self.len = HEADER + record.map_or(0, |record| record.len() as u64);
+        file.seek(SeekFrom::Start(self.len))?;
+        self.file = file;
+        self.start = start;
+        self.next = start + records;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
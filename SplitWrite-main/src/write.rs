@@ -1,15 +1,28 @@
+use crate::epochs::Registry;
 use crate::read::ReadHandle;
 use crate::Absorb;
 
-use crate::sync::{fence, Arc, AtomicUsize, MutexGuard, Ordering};
+use crate::sync::{fence, Arc, Ordering};
 use std::collections::VecDeque;
 use std::marker::PhantomData;
 use std::ops::DerefMut;
 use std::ptr::NonNull;
 #[cfg(test)]
 use std::sync::atomic::AtomicBool;
-use std::{fmt, thread};
+use std::time::{Duration, Instant};
+use std::{error, fmt, thread};
 
+mod shared;
+pub use shared::SharedWriteHandle;
+
+mod transaction;
+pub use transaction::Transaction;
+
+/// A writer handle to a left-right guarded data structure.
+///
+/// All operations on the underlying data are queued up with [`append`](Self::append) (or
+/// [`Extend::extend`]) and only become visible to readers once [`publish`](Self::publish) is
+/// called. The handle dereferences to a [`ReadHandle`], so the writer can read too.
 pub struct WriteHandle<T, O>
 where
     T: Absorb<O>,
@@ -19,15 +32,36 @@ where
     oplog: VecDeque<O>,
     swap_index: usize,
     r_handle: ReadHandle<T>,
-    last_epochs: Vec<usize>,
+    /// The copies that are neither published nor being written to, oldest first. Empty unless
+    /// the handle was created with more than two copies.
+    spares: VecDeque<Spare<T>>,
+    /// The reader epochs at each of the last `spares.len() + 1` swaps, oldest first. The oldest
+    /// was taken when the write copy stopped being the published one.
+    last_epochs: VecDeque<Vec<usize>>,
+    wait_strategy: WaitStrategy,
     #[cfg(test)]
     refreshes: usize,
     #[cfg(test)]
     is_waiting: Arc<AtomicBool>,
     first: bool,
+    /// Whether the write copy has yet to be brought up to date with [`Absorb::sync_with`].
     second: bool,
+    /// The copy that is published first. Until it is next written to, which is only after every
+    /// other copy has been, it holds exactly what was absorbed before the first publish, so the
+    /// other copies are synced with it.
+    origin: NonNull<T>,
     taken: bool,
 }
+
+/// A copy that is waiting for its turn to be written to.
+struct Spare<T> {
+    copy: NonNull<T>,
+    /// How many operations at the front of the oplog the copy has already absorbed.
+    absorbed: usize,
+    /// Whether the copy has yet to be brought up to date with [`Absorb::sync_with`].
+    unsynced: bool,
+}
+
 unsafe impl<T, O> Send for WriteHandle<T, O>
 where
     T: Absorb<O>,
@@ -49,12 +83,88 @@ where
             .field("oplog", &self.oplog)
             .field("swap_index", &self.swap_index)
             .field("r_handle", &self.r_handle)
+            .field("copies", &(self.spares.len() + 2))
+            .field("wait_strategy", &self.wait_strategy)
             .field("first", &self.first)
             .field("second", &self.second)
             .finish()
     }
 }
 
+/// How a [`WriteHandle`] waits for readers to leave the copy it is about to modify.
+///
+/// Both strategies first re-check the readers `spins` times in a tight loop, which is cheapest
+/// when guards are short-lived. The default is `Yield { spins: 20 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// After spinning, yield the thread to the scheduler between checks.
+    Yield {
+        /// How many times to check before yielding.
+        spins: usize,
+    },
+    /// After spinning, park the thread until a reader leaves the copy.
+    ///
+    /// This keeps the writer from burning a core while readers hold guards for a long time,
+    /// at the cost of a wake-up latency once they let go.
+    Park {
+        /// How many times to check before parking.
+        spins: usize,
+    },
+}
+
+impl Default for WaitStrategy {
+    fn default() -> Self {
+        WaitStrategy::Yield { spins: 20 }
+    }
+}
+
+/// A point among the pending operations of a [`WriteHandle`] that it can roll back to.
+///
+/// Created with [`WriteHandle::savepoint`], and only valid until the next publish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Savepoint {
+    version: u64,
+    pending: usize,
+}
+
+/// The error returned by [`WriteHandle::try_publish`] and [`WriteHandle::publish_timeout`] when
+/// readers have not left the copy the writer needs to modify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("readers are still accessing the copy that is to be written")
+    }
+}
+
+impl error::Error for WouldBlock {}
+
+/// A future that is pending the first time it is polled, and immediately asks to be polled again.
+#[cfg(feature = "async")]
+struct YieldNow(bool);
+
+#[cfg(feature = "async")]
+impl std::future::Future for YieldNow {
+    type Output = ();
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<()> {
+        if self.0 {
+            return std::task::Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        std::task::Poll::Pending
+    }
+}
+
+/// A copy of the data structure taken out of a [`WriteHandle`] with
+/// [`WriteHandle::take`].
+///
+/// Dropping it drops the data through [`Absorb::drop_second`].
 pub struct Taken<T: Absorb<O>, O> {
     inner: Option<Box<T>>,
     _marker: PhantomData<O>,
@@ -92,6 +202,12 @@ impl<T: Absorb<O>, O> DerefMut for Taken<T, O> {
 }
 
 impl<T: Absorb<O>, O> Taken<T, O> {
+    /// Take ownership of the boxed data structure.
+    ///
+    /// # Safety
+    ///
+    /// The returned box has not been dropped through [`Absorb::drop_second`], so any values
+    /// that were aliased between the two copies must be freed by the caller.
     pub unsafe fn into_box(mut self) -> Box<T> {
         self.inner
             .take()
@@ -113,7 +229,7 @@ where
 {
     fn take_inner(&mut self) -> Option<Taken<T, O>> {
         use std::ptr;
-        
+
         if self.taken {
             return None;
         }
@@ -123,20 +239,27 @@ where
         if self.first || !self.oplog.is_empty() {
             self.publish();
         }
-        if !self.oplog.is_empty() {
+        // every copy has to absorb every operation before the oplog empties out.
+        while !self.oplog.is_empty() {
             self.publish();
         }
-        assert!(self.oplog.is_empty());
 
         let r_handle = self.r_handle.inner.swap(ptr::null_mut(), Ordering::Release);
+        self.r_handle.generation.close();
 
+        // readers may still be in any of the copies, so wait for everyone who is reading now.
+        fence(Ordering::SeqCst);
         let epochs = Arc::clone(&self.epochs);
-        let mut epochs = epochs.lock().unwrap();
-        self.wait(&mut epochs);
+        self.last_epochs.truncate(1);
+        self.snapshot_epochs(&epochs);
+        self.wait(&epochs);
         fence(Ordering::SeqCst);
-        
+
         Absorb::drop_first(unsafe { Box::from_raw(self.w_handle.as_ptr()) });
-        
+        for spare in self.spares.drain(..) {
+            Absorb::drop_first(unsafe { Box::from_raw(spare.copy.as_ptr()) });
+        }
+
         let boxed_r_handle = unsafe { Box::from_raw(r_handle) };
 
         Some(Taken {
@@ -161,26 +284,63 @@ impl<T, O> WriteHandle<T, O>
 where
     T: Absorb<O>,
 {
-    pub(crate) fn new(w_handle: T, epochs: crate::Epochs, r_handle: ReadHandle<T>) -> Self {
+    /// `spares` are the copies beyond the first two, which must be as empty as `w_handle` was
+    /// when it was constructed.
+    pub(crate) fn new(
+        w_handle: T,
+        spares: Vec<T>,
+        epochs: crate::Epochs,
+        r_handle: ReadHandle<T>,
+    ) -> Self {
+        let w_handle = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(w_handle))) };
+        let spares: VecDeque<_> = spares
+            .into_iter()
+            .map(|copy| Spare {
+                copy: unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(copy))) },
+                absorbed: 0,
+                unsynced: true,
+            })
+            .collect();
+        r_handle.generation.register(
+            [r_handle.inner.load(Ordering::Relaxed), w_handle.as_ptr()]
+                .into_iter()
+                .chain(spares.iter().map(|spare| spare.copy.as_ptr()))
+                .map(|copy| copy as usize)
+                .collect(),
+        );
+        // nobody has read the write copy or the spares yet, so there is no one to wait for
+        // until they have been published.
+        let last_epochs = (0..=spares.len()).map(|_| Vec::new()).collect();
         Self {
             epochs,
-            
-            w_handle: unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(w_handle))) },
+
+            w_handle,
             oplog: VecDeque::new(),
             swap_index: 0,
             r_handle,
-            last_epochs: Vec::new(),
+            spares,
+            last_epochs,
+            wait_strategy: WaitStrategy::default(),
             #[cfg(test)]
             is_waiting: Arc::new(AtomicBool::new(false)),
             #[cfg(test)]
             refreshes: 0,
             first: true,
             second: true,
+            origin: w_handle,
             taken: false,
         }
     }
 
-    fn wait(&mut self, epochs: &mut MutexGuard<'_, slab::Slab<Arc<AtomicUsize>>>) {
+    fn wait(&mut self, epochs: &Registry) {
+        let departed = self.wait_until(epochs, None);
+        debug_assert!(departed);
+    }
+
+    /// Wait for readers to leave the write copy, but give up once `deadline` has passed.
+    ///
+    /// Returns false if there were still readers in the copy at the deadline.
+    fn wait_until(&mut self, epochs: &Registry, deadline: Option<Instant>) -> bool {
         let mut iter = 0;
         let mut starti = 0;
 
@@ -188,58 +348,158 @@ where
         {
             self.is_waiting.store(true, Ordering::Relaxed);
         }
-        
-        self.last_epochs.resize(epochs.capacity(), 0);
-        'retry: loop {
-            
-            for (ii, (ri, epoch)) in epochs.iter().enumerate().skip(starti) {
-                
-                if self.last_epochs[ri] % 2 == 0 {
-                    continue;
-                }
-
-                let now = epoch.load(Ordering::Acquire);
-                if now != self.last_epochs[ri] {
-                    
-                } else {
-                    
-                    starti = ii;
-
-                    if !cfg!(loom) {
-                        
-                        if iter != 20 {
-                            iter += 1;
-                        } else {
-                            thread::yield_now();
-                        }
-                    }
 
-                    #[cfg(loom)]
-                    loom::thread::yield_now();
+        let departed = loop {
+            if self.readers_departed(epochs, &mut starti) {
+                break true;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break false;
+            }
 
-                    continue 'retry;
+            if !cfg!(loom) {
+                match self.wait_strategy {
+                    WaitStrategy::Yield { spins } | WaitStrategy::Park { spins }
+                        if iter < spins =>
+                    {
+                        iter += 1;
+                    }
+                    WaitStrategy::Yield { .. } => thread::yield_now(),
+                    WaitStrategy::Park { .. } => self.park(epochs, &mut starti, deadline),
                 }
             }
-            break;
-        }
+
+            #[cfg(loom)]
+            loom::thread::yield_now();
+        };
         #[cfg(test)]
         {
             self.is_waiting.store(false, Ordering::Relaxed);
         }
+        departed
+    }
+
+    /// Park until a reader leaves its epoch, or until `deadline`.
+    fn park(&self, epochs: &Registry, starti: &mut usize, deadline: Option<Instant>) {
+        let wakeup = &self.r_handle.wakeup;
+        wakeup.arm();
+        // a reader that left before we armed will not wake us, so check once more. the fence
+        // pairs with the SeqCst epoch increment in `ReadGuard::drop`.
+        fence(Ordering::SeqCst);
+        if !self.readers_departed(epochs, starti) {
+            match deadline {
+                Some(deadline) => {
+                    thread::park_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => thread::park(),
+            }
+        }
+        wakeup.disarm();
+    }
+
+    /// Check whether every reader that was in an odd epoch when the write copy was last
+    /// published over has since moved on.
+    ///
+    /// The scan starts at slot `starti`, since readers in earlier slots are known to have
+    /// departed. If a reader is still in the copy, `starti` is left pointing at its slot.
+    fn readers_departed(&self, epochs: &Registry, starti: &mut usize) -> bool {
+        let last_epochs = self
+            .last_epochs
+            .front()
+            .expect("there is always a snapshot");
+        for (ri, epoch) in epochs.iter_from(*starti) {
+            let Some(&last) = last_epochs.get(ri) else {
+                // slots past here were allocated after the last swap.
+                break;
+            };
+            if last.is_multiple_of(2) {
+                continue;
+            }
+
+            let now = epoch.load(Ordering::Acquire);
+            if now == last {
+                *starti = ri;
+                return false;
+            }
+        }
+        true
     }
 
-    
+    /// Set how [`publish`](Self::publish) waits for readers to leave the write copy.
+    ///
+    /// See [`WaitStrategy`].
+    pub fn set_wait_strategy(&mut self, strategy: WaitStrategy) -> &mut Self {
+        self.wait_strategy = strategy;
+        self
+    }
+
+    /// Publish all operations appended since the last publish.
+    ///
+    /// This waits for all readers that are still in the copy the writer is about to modify to
+    /// leave it, then applies the pending operations and swaps the copies.
     pub fn publish(&mut self) -> &mut Self {
-        
         let epochs = Arc::clone(&self.epochs);
-        let mut epochs = epochs.lock().unwrap();
+        self.wait(&epochs);
+        self.swap_copies(&epochs);
+        self
+    }
+
+    /// Publish, unless readers are still in the copy the writer is about to modify.
+    ///
+    /// Unlike [`publish`](Self::publish), this never waits for readers. If it returns
+    /// [`WouldBlock`], nothing has been published, and operations can still be appended and
+    /// published later.
+    pub fn try_publish(&mut self) -> Result<&mut Self, WouldBlock> {
+        self.publish_before(Some(Instant::now()))
+    }
+
+    /// Publish, waiting at most `timeout` for readers to leave the copy the writer is about to
+    /// modify.
+    ///
+    /// Returns [`WouldBlock`] without publishing anything if readers remain after `timeout`.
+    pub fn publish_timeout(&mut self, timeout: Duration) -> Result<&mut Self, WouldBlock> {
+        self.publish_before(Instant::now().checked_add(timeout))
+    }
+
+    /// Publish without blocking the executor thread while readers leave the write copy.
+    ///
+    /// Between checks for lingering readers, this yields to the async runtime so that other
+    /// tasks can make progress. Once all readers have left, pending operations are applied and
+    /// the copies swapped, just like [`publish`](Self::publish).
+    #[cfg(feature = "async")]
+    pub async fn publish_async(&mut self) -> &mut Self {
+        while !self.swap_before(Some(Instant::now())) {
+            YieldNow(false).await;
+        }
+        self
+    }
 
-        self.wait(&mut epochs);
+    fn publish_before(&mut self, deadline: Option<Instant>) -> Result<&mut Self, WouldBlock> {
+        if self.swap_before(deadline) {
+            Ok(self)
+        } else {
+            Err(WouldBlock)
+        }
+    }
 
+    /// Swap the copies if readers leave the write copy before `deadline`.
+    fn swap_before(&mut self, deadline: Option<Instant>) -> bool {
+        let epochs = Arc::clone(&self.epochs);
+        if !self.wait_until(&epochs, deadline) {
+            return false;
+        }
+        self.swap_copies(&epochs);
+        true
+    }
+
+    /// Apply pending operations to the write copy and make it the published copy.
+    ///
+    /// Must only be called once all readers have left the write copy.
+    fn swap_copies(&mut self, epochs: &Registry) {
+        let was_first = self.first;
         if !self.first {
-            
             let w_handle = unsafe { self.w_handle.as_mut() };
-            
+
             let r_handle = unsafe {
                 self.r_handle
                     .inner
@@ -248,73 +508,203 @@ where
                     .unwrap()
             };
 
-            if self.second {
-                Absorb::sync_with(w_handle, r_handle);
+            let unsynced = self.second;
+            if unsynced {
+                // the origin is either the published copy, or a spare that no one writes to
+                // before this copy has been.
+                let origin = unsafe { self.origin.as_ref() };
+                Absorb::sync_with(w_handle, origin);
                 self.second = false
             }
-            
-            if self.swap_index != 0 {
-                for op in self.oplog.drain(0..self.swap_index) {
+
+            // operations every other copy has absorbed are absorbed here for the last time.
+            let absorbed = self
+                .spares
+                .iter()
+                .map(|spare| spare.absorbed)
+                .fold(self.swap_index, usize::min);
+            if absorbed != 0 {
+                debug_assert!(!unsynced, "unsynced copies are written before the origin");
+                for op in self.oplog.drain(0..absorbed) {
                     T::absorb_second(w_handle, op, r_handle);
                 }
+                self.swap_index -= absorbed;
+                for spare in &mut self.spares {
+                    spare.absorbed -= absorbed;
+                }
             }
-            
+
             for op in self.oplog.iter_mut() {
                 T::absorb_first(w_handle, op, r_handle);
             }
-            
-            self.swap_index = self.oplog.len();
-            
         } else {
             self.first = false
         }
-        
+
+        self.r_handle
+            .generation
+            .stamp(self.w_handle.as_ptr() as usize);
         let r_handle = self
             .r_handle
             .inner
             .swap(self.w_handle.as_ptr(), Ordering::Release);
 
-            
-        self.w_handle = unsafe { NonNull::new_unchecked(r_handle) };
-        
+        // the copy that was published goes to the back of the line, and the oldest copy is
+        // written to next. with two copies, that is the same copy.
+        self.spares.push_back(Spare {
+            copy: unsafe { NonNull::new_unchecked(r_handle) },
+            absorbed: self.swap_index,
+            // the only copy published without being synced is the one readers start out with.
+            unsynced: was_first,
+        });
+        self.swap_index = self.oplog.len();
+        let next = self.spares.pop_front().expect("just pushed a spare");
+        debug_assert!(next.unsynced || next.absorbed == 0);
+        self.w_handle = next.copy;
+        self.second = next.unsynced;
+
         fence(Ordering::SeqCst);
 
-        for (ri, epoch) in epochs.iter() {
-            self.last_epochs[ri] = epoch.load(Ordering::Acquire);
-        }
+        self.snapshot_epochs(epochs);
+
+        self.r_handle.generation.bump();
 
         #[cfg(test)]
         {
             self.refreshes += 1;
         }
+    }
 
-        self
+    /// Record the current reader epochs, replacing the oldest snapshot.
+    ///
+    /// Readers that register after this will see the new copy on their first enter.
+    fn snapshot_epochs(&mut self, epochs: &Registry) {
+        let mut snapshot = self.last_epochs.pop_front().unwrap_or_default();
+        snapshot.clear();
+        for (ri, epoch) in epochs.iter_from(0) {
+            // slots that are skipped were not in use, and can stay even.
+            if ri >= snapshot.len() {
+                snapshot.resize(ri + 1, 0);
+            }
+            snapshot[ri] = epoch.load(Ordering::Acquire);
+        }
+        self.last_epochs.push_back(snapshot);
+    }
+
+    /// The version of the data most recently published by this handle.
+    ///
+    /// Readers can pass this to [`ReadHandle::enter_at_least`] to make sure they observe the
+    /// writes published so far.
+    pub fn published_version(&self) -> u64 {
+        self.r_handle.generation.current()
     }
-    
+
+    /// Publish, but only if there are operations waiting to be published.
     pub fn flush(&mut self) {
         if self.has_pending_operations() {
             self.publish();
         }
     }
 
-    
+    /// Returns true if there are operations that have not yet been published.
     pub fn has_pending_operations(&self) -> bool {
-        
         self.swap_index < self.oplog.len()
     }
 
-    
+    /// Discard every operation appended since the last publish.
+    ///
+    /// The discarded operations are dropped through [`Absorb::drop_op`]. Operations appended
+    /// before the first publish are absorbed into the write copy right away, and so cannot be
+    /// rolled back.
+    pub fn rollback(&mut self) -> &mut Self {
+        self.discard_from(self.swap_index);
+        self
+    }
+
+    /// Mark the current point among the pending operations, to roll back to later with
+    /// [`rollback_to`](Self::rollback_to).
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            version: self.published_version(),
+            pending: self.oplog.len() - self.swap_index,
+        }
+    }
+
+    /// Discard the operations appended since `savepoint` was taken.
+    ///
+    /// Savepoints taken after `savepoint` can no longer be rolled back to. As with
+    /// [`rollback`](Self::rollback), operations appended before the first publish are not
+    /// affected.
+    ///
+    /// # Panics
+    ///
+    /// If there has been a publish since `savepoint` was taken, or the handle has already been
+    /// rolled back past it.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> &mut Self {
+        let kept = self.kept_by(savepoint);
+        self.discard_from(self.swap_index + kept);
+        self
+    }
+
+    /// The number of pending operations that rolling back to `savepoint` keeps.
+    ///
+    /// Panics as [`rollback_to`](Self::rollback_to) does if `savepoint` is no longer valid.
+    pub(crate) fn kept_by(&self, savepoint: Savepoint) -> usize {
+        assert_eq!(
+            savepoint.version,
+            self.published_version(),
+            "savepoint was taken before the last publish"
+        );
+        let pending = self.oplog.len() - self.swap_index;
+        assert!(
+            savepoint.pending <= pending,
+            "already rolled back past the savepoint"
+        );
+        savepoint.pending
+    }
+
+    /// The operations appended since the last publish, in order.
+    pub(crate) fn pending(&self) -> impl Iterator<Item = &O> {
+        self.oplog.range(self.swap_index..)
+    }
+
+    /// The operations appended since `savepoint` was taken, or every pending operation if it is
+    /// no longer valid.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub(crate) fn pending_since(&self, savepoint: Savepoint) -> impl Iterator<Item = &O> {
+        let pending = self.oplog.len() - self.swap_index;
+        let seen = if savepoint.version == self.published_version() && savepoint.pending <= pending
+        {
+            savepoint.pending
+        } else {
+            0
+        };
+        self.oplog.range(self.swap_index + seen..)
+    }
+
+    fn discard_from(&mut self, index: usize) {
+        for op in self.oplog.drain(index..) {
+            T::drop_op(op);
+        }
+    }
+
+    /// Append an operation to be applied at the next [`publish`](Self::publish).
     pub fn append(&mut self, op: O) -> &mut Self {
         self.extend(std::iter::once(op));
         self
     }
-    
+    /// Returns a raw pointer to the write copy of the data.
+    ///
+    /// The copy may still be accessed by readers until the next call to
+    /// [`publish`](Self::publish) has waited for them, so dereferencing it is only safe for
+    /// operations that cannot be observed by concurrent readers.
     pub fn raw_write_handle(&mut self) -> NonNull<T> {
         self.w_handle
     }
-    
+    /// Publish all pending operations and take the data out of the handle.
+    ///
+    /// Readers will see [`ReadHandle::enter`] return `None` after this.
     pub fn take(mut self) -> Taken<T, O> {
-        
         self.take_inner()
             .expect("inner is only taken here then self is dropped")
     }
@@ -335,17 +725,16 @@ impl<T, O> Extend<O> for WriteHandle<T, O>
 where
     T: Absorb<O>,
 {
-    
+    /// Append multiple operations to be applied at the next [`publish`](Self::publish).
     fn extend<I>(&mut self, ops: I)
     where
         I: IntoIterator<Item = O>,
     {
         if self.first {
-            
             let mut w_inner = self.raw_write_handle();
             let w_inner = unsafe { w_inner.as_mut() };
             let r_handle = self.enter().expect("map has not yet been destroyed");
-            
+
             for op in ops {
                 Absorb::absorb_second(w_inner, op, &*r_handle);
             }
@@ -355,26 +744,26 @@ where
     }
 }
 
-
 #[allow(dead_code)]
 struct CheckWriteHandleSend;
 
 #[cfg(test)]
 mod tests {
-    use crate::sync::{AtomicUsize, Mutex, Ordering};
+    use crate::epochs::Registry;
+    use crate::sync::Ordering;
     use crate::Absorb;
-    use slab::Slab;
+    use std::collections::VecDeque;
     include!("./utilities.rs");
 
     #[test]
     fn append_test() {
         let (mut w, _r) = crate::new::<i32, _>();
-        assert_eq!(w.first, true);
+        assert!(w.first);
         w.append(CounterAddOp(1));
         assert_eq!(w.oplog.len(), 0);
-        assert_eq!(w.first, true);
+        assert!(w.first);
         w.publish();
-        assert_eq!(w.first, false);
+        assert!(!w.first);
         w.append(CounterAddOp(2));
         w.append(CounterAddOp(3));
         assert_eq!(w.oplog.len(), 2);
@@ -382,7 +771,6 @@ mod tests {
 
     #[test]
     fn take_test() {
-        
         let (mut w, _r) = crate::new_from_empty::<i32, _>(2);
         w.append(CounterAddOp(1));
         w.publish();
@@ -390,7 +778,6 @@ mod tests {
         w.publish();
         assert_eq!(*w.take(), 4);
 
-        
         let (mut w, _r) = crate::new_from_empty::<i32, _>(2);
         w.append(CounterAddOp(1));
         w.publish();
@@ -399,24 +786,21 @@ mod tests {
         w.append(CounterAddOp(2));
         assert_eq!(*w.take(), 6);
 
-        
         let (mut w, _r) = crate::new_from_empty::<i32, _>(2);
         w.append(CounterAddOp(1));
         w.publish();
         w.append(CounterAddOp(1));
         assert_eq!(*w.take(), 4);
-        
+
         let (mut w, _r) = crate::new_from_empty::<i32, _>(2);
         w.append(CounterAddOp(1));
         assert_eq!(*w.take(), 3);
 
-        
         let (mut w, _r) = crate::new_from_empty::<i32, _>(2);
         w.append(CounterAddOp(1));
         w.publish();
         assert_eq!(*w.take(), 3);
 
-        
         let (w, _r) = crate::new_from_empty::<i32, _>(2);
         assert_eq!(*w.take(), 2);
     }
@@ -426,47 +810,83 @@ mod tests {
         use std::sync::{Arc, Barrier};
         use std::thread;
         let (mut w, _r) = crate::new::<i32, _>();
-        
-        let test_epochs: crate::Epochs = Default::default();
-        let mut test_epochs = test_epochs.lock().unwrap();
-        
-        w.wait(&mut test_epochs);
-        
-        let held_epoch = Arc::new(AtomicUsize::new(1));
-
-        w.last_epochs = vec![2, 2, 1];
-        let mut epochs_slab = Slab::new();
-        epochs_slab.insert(Arc::new(AtomicUsize::new(2)));
-        epochs_slab.insert(Arc::new(AtomicUsize::new(2)));
-        epochs_slab.insert(Arc::clone(&held_epoch));
+
+        let test_epochs = Registry::default();
+
+        w.wait(&test_epochs);
+
+        w.last_epochs = VecDeque::from([vec![2, 2, 1]]);
+        let test_epochs = Arc::new(Registry::default());
+        for epoch in [2, 2, 1] {
+            let ri = test_epochs.register();
+            test_epochs.epoch(ri).store(epoch, Ordering::Relaxed);
+        }
+        let held = 2;
 
         let barrier = Arc::new(Barrier::new(2));
 
         let is_waiting = Arc::clone(&w.is_waiting);
-        
+
         let is_waiting_v = is_waiting.load(Ordering::Relaxed);
-        assert_eq!(false, is_waiting_v);
+        assert!(!is_waiting_v);
 
         let barrier2 = Arc::clone(&barrier);
-        let test_epochs = Arc::new(Mutex::new(epochs_slab));
+        let wait_epochs = Arc::clone(&test_epochs);
         let wait_handle = thread::spawn(move || {
             barrier2.wait();
-            let mut test_epochs = test_epochs.lock().unwrap();
-            w.wait(&mut test_epochs);
+            w.wait(&wait_epochs);
         });
 
         barrier.wait();
-        
+
         while !is_waiting.load(Ordering::Relaxed) {
             thread::yield_now();
         }
 
-        held_epoch.fetch_add(1, Ordering::SeqCst);
+        test_epochs.epoch(held).fetch_add(1, Ordering::SeqCst);
 
-        
         let _ = wait_handle.join();
     }
 
+    #[test]
+    fn rollback_to_savepoints() {
+        let (mut w, r) = crate::new::<i32, _>();
+        w.append(CounterAddOp(1));
+        w.publish();
+
+        w.append(CounterAddOp(2));
+        let outer = w.savepoint();
+        w.append(CounterAddOp(4));
+        let inner = w.savepoint();
+        w.append(CounterAddOp(8));
+        w.rollback_to(inner);
+        assert_eq!(w.oplog.len() - w.swap_index, 2);
+        w.rollback_to(inner).rollback_to(outer);
+        assert_eq!(w.oplog.len() - w.swap_index, 1);
+        w.publish();
+        assert_eq!(*r.enter().unwrap(), 3);
+
+        w.append(CounterAddOp(16));
+        w.rollback();
+        assert!(!w.has_pending_operations());
+        w.publish();
+        assert_eq!(*r.enter().unwrap(), 3);
+        // the other copy still needs the operations that were published.
+        w.publish();
+        assert_eq!(*r.enter().unwrap(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "before the last publish")]
+    fn savepoints_do_not_survive_publish() {
+        let (mut w, _r) = crate::new::<i32, _>();
+        w.publish();
+        let savepoint = w.savepoint();
+        w.append(CounterAddOp(1));
+        w.publish();
+        w.rollback_to(savepoint);
+    }
+
     #[test]
     fn flush_noblock() {
         let (mut w, r) = crate::new::<i32, _>();
@@ -474,9 +894,8 @@ mod tests {
         w.publish();
         assert_eq!(*r.enter().unwrap(), 42);
 
-        
         let _count = r.enter();
-        
+
         assert_eq!(w.oplog.iter().skip(w.swap_index).count(), 0);
         assert!(!w.has_pending_operations());
     }
@@ -484,7 +903,7 @@ mod tests {
     #[test]
     fn flush_no_refresh() {
         let (mut w, _) = crate::new::<i32, _>();
-        
+
         assert!(!w.has_pending_operations());
         w.publish();
         assert!(!w.has_pending_operations());
@@ -502,9 +921,71 @@ mod tests {
         assert!(!w.has_pending_operations());
         assert_eq!(w.refreshes, 3);
 
-        
         assert!(!w.has_pending_operations());
         w.publish();
         assert_eq!(w.refreshes, 4);
     }
+
+    #[test]
+    fn try_publish_with_lingering_reader() {
+        use crate::WouldBlock;
+        use std::time::Duration;
+
+        let (mut w, r) = crate::new::<i32, _>();
+        w.append(CounterAddOp(1));
+        w.publish();
+
+        // the reader is in the copy that the next publish makes the write copy.
+        let guard = r.enter().unwrap();
+        w.append(CounterAddOp(1));
+        assert!(w.try_publish().is_ok());
+
+        w.append(CounterAddOp(1));
+        assert_eq!(w.try_publish().unwrap_err(), WouldBlock);
+        assert_eq!(
+            w.publish_timeout(Duration::from_millis(10)).unwrap_err(),
+            WouldBlock
+        );
+        assert!(w.has_pending_operations());
+        assert_eq!(w.refreshes, 2);
+        assert_eq!(*guard, 1);
+
+        drop(guard);
+        assert!(w.try_publish().is_ok());
+        assert_eq!(*r.enter().unwrap(), 3);
+        assert!(w.publish_timeout(Duration::from_millis(10)).is_ok());
+        assert_eq!(w.refreshes, 4);
+    }
+
+    #[test]
+    fn park_until_reader_leaves() {
+        use crate::WaitStrategy;
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let (mut w, r) = crate::new::<i32, _>();
+        w.set_wait_strategy(WaitStrategy::Park { spins: 0 });
+        w.append(CounterAddOp(1));
+        w.publish();
+
+        let (entered, entered_rx) = mpsc::channel();
+        let reader = {
+            let r = r.clone();
+            std::thread::spawn(move || {
+                let guard = r.enter().unwrap();
+                entered.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                drop(guard);
+            })
+        };
+        entered_rx.recv().unwrap();
+        w.append(CounterAddOp(1));
+        w.publish();
+
+        w.append(CounterAddOp(1));
+        assert!(w.publish_timeout(Duration::from_millis(1)).is_err());
+        w.publish();
+        reader.join().unwrap();
+        assert_eq!(*r.enter().unwrap(), 3);
+    }
 }
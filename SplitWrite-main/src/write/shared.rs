@@ -0,0 +1,226 @@
+use super::WriteHandle;
+use crate::{Absorb, ReadHandle, ReadHandleFactory};
+use std::fmt;
+use std::sync::{mpsc, Arc, Mutex};
+
+/// A [`WriteHandle`] that many threads can append operations through at once.
+///
+/// Appending sends the operation down a channel, so producers never wait for each other or
+/// for a publish in progress. Operations are handed to the `WriteHandle` in the order they
+/// were sent, and are absorbed in that order; operations appended by one thread keep the
+/// order that thread appended them in.
+///
+/// Whichever thread calls [`flush_and_wait`](Self::flush_and_wait) moves the queued operations
+/// into the oplog and publishes them. To publish from a dedicated thread instead, see
+/// [`WriteHandle::into_auto_publisher`].
+///
+/// Clones share the same `WriteHandle`, which is dropped, and so publishes anything still
+/// queued, along with the last clone.
+pub struct SharedWriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    ops: mpsc::Sender<O>,
+    writer: Arc<Mutex<Writer<T, O>>>,
+    factory: ReadHandleFactory<T>,
+}
+
+struct Writer<T, O>
+where
+    T: Absorb<O>,
+{
+    handle: WriteHandle<T, O>,
+    queued: mpsc::Receiver<O>,
+}
+
+impl<T, O> Writer<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Move queued operations into the oplog, and return whether there were any.
+    fn drain(&mut self) -> bool {
+        let mut any = false;
+        while let Ok(op) = self.queued.try_recv() {
+            self.handle.append(op);
+            any = true;
+        }
+        any
+    }
+}
+
+impl<T, O> Drop for Writer<T, O>
+where
+    T: Absorb<O>,
+{
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
+
+impl<T, O> Clone for SharedWriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            ops: self.ops.clone(),
+            writer: Arc::clone(&self.writer),
+            factory: self.factory.clone(),
+        }
+    }
+}
+
+impl<T, O> fmt::Debug for SharedWriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedWriteHandle")
+            .field("factory", &self.factory)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, O> From<WriteHandle<T, O>> for SharedWriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    fn from(handle: WriteHandle<T, O>) -> Self {
+        let (ops, queued) = mpsc::channel();
+        let factory = handle.factory();
+        Self {
+            ops,
+            writer: Arc::new(Mutex::new(Writer { handle, queued })),
+            factory,
+        }
+    }
+}
+
+impl<T, O> WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Turn this handle into one that many threads can append operations through.
+    pub fn into_shared(self) -> SharedWriteHandle<T, O> {
+        SharedWriteHandle::from(self)
+    }
+}
+
+impl<T, O> SharedWriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Queue an operation to be applied at the next publish.
+    pub fn append(&self, op: O) {
+        // the receiver lives as long as `self.writer`, so this cannot fail.
+        let _ = self.ops.send(op);
+    }
+
+    /// Queue multiple operations to be applied at the next publish, in order.
+    pub fn extend<I>(&self, ops: I)
+    where
+        I: IntoIterator<Item = O>,
+    {
+        for op in ops {
+            self.append(op);
+        }
+    }
+
+    /// Publish every operation this thread has appended, and return once readers can see them.
+    ///
+    /// Operations other threads have queued are published along with them. If another thread
+    /// is already publishing, this waits for it to finish first, and only publishes again if
+    /// that publish did not cover everything.
+    pub fn flush_and_wait(&self) {
+        let mut writer = self.writer.lock().unwrap();
+        // anything another thread took off the queue was published before it released the lock.
+        if writer.drain() || writer.handle.has_pending_operations() {
+            writer.handle.publish();
+        }
+    }
+
+    /// A factory for read handles to the data.
+    pub fn factory(&self) -> ReadHandleFactory<T> {
+        self.factory.clone()
+    }
+
+    /// A new read handle to the data.
+    pub fn reader(&self) -> ReadHandle<T> {
+        self.factory.handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Applied, Apply};
+    use std::thread;
+
+    #[test]
+    fn producers_keep_their_order() {
+        let (w, r) = crate::new::<Applied<Vec<(usize, usize)>>, Apply<Vec<(usize, usize)>>>();
+        let w = w.into_shared();
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let w = w.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        w.append(Apply::new(move |v: &mut Vec<_>| v.push((p, i))));
+                        if i % 10 == 0 {
+                            w.flush_and_wait();
+                            let r = w.reader();
+                            let seen = r.enter().unwrap();
+                            assert!(seen.contains(&(p, i)));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        w.flush_and_wait();
+
+        let seen = r.enter().unwrap();
+        assert_eq!(seen.len(), 400);
+        for p in 0..4 {
+            let mine: Vec<_> = seen
+                .iter()
+                .filter(|&&(q, _)| q == p)
+                .map(|&(_, i)| i)
+                .collect();
+            assert_eq!(mine, (0..100).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn last_clone_publishes_queued_ops() {
+        let (w, r) = crate::new::<Applied<i32>, Apply<i32>>();
+        let w = w.into_shared();
+        w.append(Apply::new(|x| *x += 1));
+        w.flush_and_wait();
+        assert_eq!(**r.enter().unwrap(), 1);
+        // nothing new, so no publish.
+        let published = r.generation();
+        w.flush_and_wait();
+        assert_eq!(r.generation(), published);
+
+        let (entered, entered_rx) = std::sync::mpsc::channel();
+        let reader = {
+            let (old, new) = (r.clone(), r.clone());
+            thread::spawn(move || {
+                // holding on to the published copy keeps the dropping writer from finishing, so
+                // the copy it publishes on the way out can still be entered.
+                let before = old.enter().unwrap();
+                entered.send(()).unwrap();
+                new.wait_for_generation(published + 1).unwrap();
+                let after = new.enter().unwrap();
+                assert_eq!((**before, **after), (1, 2));
+            })
+        };
+        entered_rx.recv().unwrap();
+        w.clone().append(Apply::new(|x| *x += 1));
+        drop(w);
+        reader.join().unwrap();
+        assert!(r.was_dropped());
+    }
+}
@@ -0,0 +1,166 @@
+use super::{Savepoint, WriteHandle};
+use crate::sync::Ordering;
+use crate::{Absorb, Preview};
+use std::fmt;
+
+/// A group of operations that is either appended to a [`WriteHandle`] as a whole, or not at all.
+///
+/// Passed to the closure given to [`WriteHandle::transaction`], which can check preconditions
+/// against [`current`](Self::current), [`published`](Self::published) and
+/// [`pending`](Self::pending) before appending.
+pub struct Transaction<'w, T, O>
+where
+    T: Absorb<O>,
+{
+    handle: &'w mut WriteHandle<T, O>,
+    savepoint: Savepoint,
+    committed: bool,
+    /// A clone of the published data, and how many pending operations it has previewed.
+    scratch: Option<(T, usize)>,
+    /// The operations appended so far, if the handle has never published. Its operations are
+    /// absorbed as soon as they are appended until then, so they are held back until commit.
+    held: Option<Vec<O>>,
+}
+
+impl<T, O> fmt::Debug for Transaction<'_, T, O>
+where
+    T: Absorb<O> + fmt::Debug,
+    O: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("savepoint", &self.savepoint)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, O> WriteHandle<T, O>
+where
+    T: Absorb<O>,
+{
+    /// Run `f` to append a group of operations, keeping them only if it returns `Ok`.
+    ///
+    /// If `f` returns an error or panics, every operation it appended is rolled back, so a
+    /// publish never exposes part of a transaction. Operations pending from before the
+    /// transaction are kept either way.
+    ///
+    /// On a handle that has never published, whose operations are otherwise absorbed into the
+    /// write copy right away, the transaction holds on to its operations and only appends them
+    /// once `f` returns `Ok`.
+    pub fn transaction<F, R, E>(&mut self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Transaction<'_, T, O>) -> Result<R, E>,
+    {
+        let mut tx = Transaction {
+            savepoint: self.savepoint(),
+            held: self.first.then(Vec::new),
+            handle: self,
+            committed: false,
+            scratch: None,
+        };
+        let result = f(&mut tx);
+        if result.is_ok() {
+            if let Some(held) = tx.held.take() {
+                tx.handle.extend(held);
+            }
+            tx.committed = true;
+        }
+        result
+    }
+}
+
+impl<T, O> Transaction<'_, T, O>
+where
+    T: Absorb<O>,
+{
+    /// The data as of the last publish, which is what readers currently see.
+    ///
+    /// This does not reflect [`pending`](Self::pending) operations; see
+    /// [`current`](Self::current) for that.
+    pub fn published(&self) -> &T {
+        let published = self.handle.r_handle.inner.load(Ordering::Acquire);
+        // Safety: the writer only ever modifies a copy that is not published, which takes a
+        // publish, and we are holding on to the writer.
+        unsafe { published.as_ref() }.expect("the handle has not been taken")
+    }
+
+    /// Every operation appended since the last publish, in order, including those appended by
+    /// this transaction.
+    ///
+    /// Before the first publish, only the operations of this transaction are pending; those
+    /// appended before it are already in the write copy, and show up in
+    /// [`current`](Self::current).
+    pub fn pending(&self) -> impl Iterator<Item = &O> {
+        self.handle.pending().chain(self.held.iter().flatten())
+    }
+
+    /// The data as the next publish will leave it, with every pending operation applied,
+    /// including those appended by this transaction.
+    ///
+    /// The first call clones the published data, or before the first publish the write copy,
+    /// which holds every operation appended so far. Every call applies the operations
+    /// appended since the last one to that clone with [`Preview::preview`]. The copies readers
+    /// use are not touched, and the operations are not counted as absorbed.
+    pub fn current(&mut self) -> &T
+    where
+        T: Preview<O>,
+    {
+        let base = if self.held.is_some() {
+            // Safety: the handle has never published, so no reader has seen the write copy, and
+            // we are holding on to the writer. the write copy holds every operation appended
+            // before the transaction, while the published copy holds none of them.
+            unsafe { self.handle.w_handle.as_ref() }
+        } else {
+            let published = self.handle.r_handle.inner.load(Ordering::Acquire);
+            // Safety: as in `published`.
+            unsafe { published.as_ref() }.expect("the handle has not been taken")
+        };
+        let (scratch, previewed) = self.scratch.get_or_insert_with(|| (base.clone(), 0));
+        let pending = self.handle.pending().chain(self.held.iter().flatten());
+        for op in pending.skip(*previewed) {
+            scratch.preview(op);
+            *previewed += 1;
+        }
+        scratch
+    }
+
+    /// Append an operation as part of this transaction.
+    pub fn append(&mut self, op: O) -> &mut Self {
+        match &mut self.held {
+            Some(held) => held.push(op),
+            None => {
+                self.handle.append(op);
+            }
+        }
+        self
+    }
+}
+
+impl<T, O> Extend<O> for Transaction<'_, T, O>
+where
+    T: Absorb<O>,
+{
+    fn extend<I>(&mut self, ops: I)
+    where
+        I: IntoIterator<Item = O>,
+    {
+        match &mut self.held {
+            Some(held) => held.extend(ops),
+            None => self.handle.extend(ops),
+        }
+    }
+}
+
+impl<T, O> Drop for Transaction<'_, T, O>
+where
+    T: Absorb<O>,
+{
+    fn drop(&mut self) {
+        if let Some(held) = self.held.take() {
+            held.into_iter().for_each(T::drop_op);
+        }
+        if !self.committed {
+            self.handle.rollback_to(self.savepoint);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "async")]
+
+use splitwrite::{Applied, Apply};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+
+struct Noop;
+
+impl Wake for Noop {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Poll `fut` to completion on this thread, returning its output and how often it was polled.
+fn block_on<F: Future>(fut: F) -> (F::Output, usize) {
+    let waker = Waker::from(Arc::new(Noop));
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(fut);
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return (out, polls);
+        }
+    }
+}
+
+#[test]
+fn publish_async_yields_to_lingering_reader() {
+    let (mut w, r) = splitwrite::new::<Applied<i32>, Apply<i32>>();
+    w.apply(|x| *x += 1);
+    w.publish();
+    w.apply(|x| *x += 1);
+    w.publish();
+
+    // park a reader in the copy that the publish after next has to write to.
+    let (entered, entered_rx) = mpsc::channel();
+    let (leave, leave_rx) = mpsc::channel::<()>();
+    let reader = {
+        let r = r.clone();
+        thread::spawn(move || {
+            let guard = r.enter().unwrap();
+            entered.send(*guard).unwrap();
+            leave_rx.recv().unwrap();
+        })
+    };
+    assert_eq!(entered_rx.recv().unwrap(), Applied(2));
+    w.apply(|x| *x += 1);
+    w.publish();
+
+    w.apply(|x| *x += 1);
+    let mut leave = Some(leave);
+    let (_, polls) = block_on(async {
+        let publish = w.publish_async();
+        let mut publish = pin!(publish);
+        std::future::poll_fn(|cx| {
+            let poll = publish.as_mut().poll(cx);
+            // only let the reader go once the publish has had to yield at least once.
+            if poll.is_pending() {
+                if let Some(leave) = leave.take() {
+                    leave.send(()).unwrap();
+                }
+            }
+            poll.map(|_| ())
+        })
+        .await
+    });
+    reader.join().unwrap();
+
+    assert!(polls > 1);
+    assert_eq!(**r.enter().unwrap(), 4);
+}
+
+#[test]
+fn publish_async_without_readers_is_ready() {
+    let (mut w, r) = splitwrite::new::<Applied<Vec<i32>>, Apply<Vec<i32>>>();
+    w.apply(|v| v.push(1));
+    let (_, polls) = block_on(async {
+        w.publish_async().await.apply(|v| v.push(2));
+        w.publish_async().await;
+    });
+    assert_eq!(polls, 1);
+    assert_eq!(**r.enter().unwrap(), [1, 2]);
+}
+
+#[test]
+fn changed_reports_each_new_generation_once() {
+    let (mut w, r) = splitwrite::new::<Applied<i32>, Apply<i32>>();
+    w.publish();
+
+    let writer = thread::spawn(move || {
+        for _ in 0..3 {
+            w.apply(|x| *x += 1);
+            w.publish();
+        }
+    });
+
+    let ((), _) = block_on(async {
+        let mut last = 0;
+        while let Some(g) = r.changed().await {
+            assert!(g > last);
+            assert!(r.generation() >= g);
+            last = g;
+            if g >= 4 {
+                break;
+            }
+        }
+        assert!(last >= 4);
+    });
+    writer.join().unwrap();
+    assert!(r.was_dropped());
+    assert_eq!(block_on(r.changed()).0, None);
+}
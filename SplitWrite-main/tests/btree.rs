@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+struct Value {
+    v: i32,
+    live: Arc<AtomicI64>,
+}
+
+impl Value {
+    fn new(v: i32, live: &Arc<AtomicI64>) -> Self {
+        live.fetch_add(1, Ordering::SeqCst);
+        Self {
+            v,
+            live: Arc::clone(live),
+        }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        let was = self.live.fetch_sub(1, Ordering::SeqCst);
+        assert!(was > 0);
+    }
+}
+
+#[test]
+fn btree() {
+    let (mut w, r) = splitwrite::btree::new();
+    for ts in (0..100).step_by(10) {
+        w.insert(ts, ts.to_string());
+    }
+    w.publish();
+
+    {
+        let map = r.enter().unwrap();
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.first_key_value(), Some((&0, &"0".to_string())));
+        assert_eq!(map.last_key_value().map(|(k, _)| *k), Some(90));
+        let keys: Vec<_> = map.range(25..=50).map(|(k, _)| *k).collect();
+        assert_eq!(keys, [30, 40, 50]);
+        let keys: Vec<_> = map.range(..20).rev().map(|(k, _)| *k).collect();
+        assert_eq!(keys, [10, 0]);
+    }
+
+    w.remove(0).split_off(50).insert(5, "five".to_string());
+    w.publish();
+    assert_eq!(r.get(&5).as_deref().map(String::as_str), Some("five"));
+    assert!(!r.contains_key(&0));
+
+    // both copies must have seen the same operations.
+    w.publish();
+    let keys: Vec<_> = r.enter().unwrap().keys().copied().collect();
+    assert_eq!(keys, [5, 10, 20, 30, 40]);
+
+    w.clear();
+    w.publish();
+    assert!(r.is_empty());
+    assert_eq!(r.enter().unwrap().first_key_value(), None);
+}
+
+#[test]
+fn values_dropped_once() {
+    let live = Arc::new(AtomicI64::new(0));
+    let expect = |n| assert_eq!(live.load(Ordering::SeqCst), n);
+
+    let (mut w, r) = splitwrite::btree::new();
+    for i in 0..10 {
+        w.insert(i, Value::new(i, &live));
+    }
+    w.publish();
+    expect(10);
+
+    w.insert(0, Value::new(10, &live));
+    w.remove(1);
+    w.split_off(5);
+    w.publish();
+    expect(11);
+    w.publish();
+    expect(4);
+    assert_eq!(
+        r.enter().unwrap().values().map(|v| v.v).collect::<Vec<_>>(),
+        [10, 2, 3, 4]
+    );
+
+    w.clear();
+    w.insert(0, Value::new(0, &live));
+    w.publish();
+    w.publish();
+    expect(1);
+
+    drop(w);
+    expect(0);
+}
@@ -0,0 +1,93 @@
+use splitwrite::{Absorb, Applied, Apply};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// How many copies have absorbed an operation, and how many of them as the last copy.
+#[derive(Debug, Default)]
+struct Seen {
+    first: AtomicUsize,
+    second: AtomicUsize,
+}
+
+struct Push(usize, Arc<Seen>);
+
+#[derive(Clone, Default, Debug, PartialEq)]
+struct Log(Vec<usize>);
+
+impl Absorb<Push> for Log {
+    fn absorb_first(&mut self, operation: &mut Push, _: &Self) {
+        operation.1.first.fetch_add(1, Ordering::SeqCst);
+        self.0.push(operation.0);
+    }
+
+    fn absorb_second(&mut self, operation: Push, _: &Self) {
+        operation.1.second.fetch_add(1, Ordering::SeqCst);
+        self.0.push(operation.0);
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+#[test]
+fn every_copy_sees_every_operation() {
+    for copies in 2..=5 {
+        let (mut w, r) = splitwrite::new_with_copies::<Log, Push>(copies);
+        let seen: Vec<_> = (0..=20).map(|_| Arc::new(Seen::default())).collect();
+
+        let push = |i: usize| Push(i, Arc::clone(&seen[i]));
+
+        w.append(push(0));
+        w.publish();
+        for i in 1..20 {
+            w.append(push(i));
+            w.publish();
+            assert_eq!(r.enter().unwrap().0, (0..=i).collect::<Vec<_>>());
+            assert_eq!(r.enter().unwrap().version(), i as u64 + 1);
+        }
+        w.append(push(20));
+        assert_eq!(w.take().0, (0..=20).collect::<Vec<_>>());
+
+        // the first operation is absorbed straight into the write copy, which the other copies
+        // are then synced with.
+        assert_eq!(seen[0].first.load(Ordering::SeqCst), 0, "{copies} copies");
+        assert_eq!(seen[0].second.load(Ordering::SeqCst), 1, "{copies} copies");
+        for (i, seen) in seen.iter().enumerate().skip(1) {
+            let first = seen.first.load(Ordering::SeqCst);
+            let second = seen.second.load(Ordering::SeqCst);
+            assert_eq!(first, copies - 1, "operation {i} with {copies} copies");
+            assert_eq!(second, 1, "operation {i} with {copies} copies");
+        }
+    }
+}
+
+#[test]
+fn only_the_oldest_copy_is_waited_for() {
+    let (mut w, r) = splitwrite::new_with_copies::<Applied<i32>, Apply<i32>>(3);
+    w.publish();
+    w.apply(|x| *x += 1);
+    w.publish();
+
+    let guard = r.enter().unwrap();
+    assert_eq!(**guard, 1);
+    for _ in 0..2 {
+        w.apply(|x| *x += 1);
+        assert!(w.try_publish().is_ok());
+    }
+    assert_eq!(**r.clone().enter().unwrap(), 3);
+
+    // the next copy to be written is the one the guard is still reading.
+    w.apply(|x| *x += 1);
+    assert!(w.try_publish().is_err());
+    assert_eq!(**guard, 1);
+    drop(guard);
+    assert!(w.try_publish().is_ok());
+    assert_eq!(**r.enter().unwrap(), 4);
+}
+
+#[test]
+#[should_panic(expected = "at least two copies")]
+fn one_copy_is_not_enough() {
+    let _ = splitwrite::new_from_empty_with_copies::<Applied<i32>, Apply<i32>>(Applied(0), 1);
+}
@@ -2,7 +2,7 @@ use std::cell::Cell;
 use std::collections::VecDeque;
 use std::rc::Rc;
 
-use left_right::{
+use splitwrite::{
     aliasing::{Aliased, DropBehavior},
     Absorb, ReadHandle,
 };
@@ -76,7 +76,6 @@ impl Absorb<Op> for Deque {
     }
 
     fn absorb_second(&mut self, operation: Op, _other: &Self) {
-       
         let with_drop: &mut VecDeque<Aliased<Value, DoDrop>> =
             unsafe { &mut *(self as *mut _ as *mut _) };
         match operation {
@@ -94,19 +93,15 @@ impl Absorb<Op> for Deque {
         self.extend(first.iter().map(|v| unsafe { v.alias() }));
     }
 
-    fn drop_first(self: Box<Self>) {
-        
-    }
+    fn drop_first(self: Box<Self>) {}
 
     fn drop_second(self: Box<Self>) {
-        
         let with_drop: Box<VecDeque<Aliased<Value, DoDrop>>> =
             unsafe { Box::from_raw(Box::into_raw(self) as *mut _ as *mut _) };
         drop(with_drop);
     }
 }
 
-
 #[test]
 fn deque() {
     let registry = Rc::new(ValueRegistry::new());
@@ -117,7 +112,7 @@ fn deque() {
         assert!(guard.iter().map(|v| &v.v).eq(expected.iter()));
     };
 
-    let (mut w, r) = left_right::new::<Deque, Op>();
+    let (mut w, r) = splitwrite::new::<Deque, Op>();
     w.append(Op::PushBack(mkval(1)));
     w.append(Op::PushBack(mkval(2)));
     w.append(Op::PushBack(mkval(3)));
@@ -141,7 +136,7 @@ fn deque() {
 
     w.append(Op::PopFront);
     w.publish();
-    
+
     registry.expect(2);
     expect(&r, &[4]);
 
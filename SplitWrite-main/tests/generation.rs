@@ -0,0 +1,82 @@
+use splitwrite::{Applied, Apply};
+use std::sync::mpsc;
+use std::thread;
+
+#[test]
+fn generation_counts_publishes() {
+    let (mut w, r) = splitwrite::new::<Applied<i32>, Apply<i32>>();
+    assert_eq!(r.generation(), 0);
+    w.publish();
+    w.apply(|x| *x += 1);
+    w.publish();
+    assert_eq!(r.generation(), 2);
+    assert_eq!(r.wait_for_generation(1), Some(2));
+}
+
+#[test]
+fn wait_for_generation_sees_published_data() {
+    let (mut w, r) = splitwrite::new::<Applied<i32>, Apply<i32>>();
+    let factory = r.factory();
+    let (checked, checked_rx) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        let r = factory.handle();
+        let g = r.wait_for_generation(3).unwrap();
+        assert!(g >= 3);
+        assert!(**r.enter().unwrap() >= 3);
+        checked.send(()).unwrap();
+        // the writer goes away before the tenth publish.
+        assert_eq!(r.wait_for_generation(10), None);
+    });
+
+    for _ in 0..5 {
+        w.apply(|x| *x += 1);
+        w.publish();
+    }
+    checked_rx.recv().unwrap();
+    drop(w);
+    reader.join().unwrap();
+}
+
+#[test]
+fn guards_report_the_version_they_read() {
+    let (mut w, r) = splitwrite::new::<Applied<i32>, Apply<i32>>();
+    assert_eq!(r.enter().unwrap().version(), 0);
+    w.publish();
+    let first = r.enter().unwrap();
+    assert_eq!(first.version(), 1);
+
+    w.apply(|x| *x += 1);
+    w.publish();
+    assert_eq!(w.published_version(), 2);
+    let second = r.enter_at_least(w.published_version()).unwrap();
+    assert_eq!(second.version(), 2);
+    assert_eq!(**second, 1);
+    // a guard keeps reporting the version it was taken out on.
+    assert_eq!(first.version(), 1);
+    drop((first, second));
+
+    w.apply(|x| *x += 1);
+    w.publish();
+    let guard = r.enter().unwrap();
+    assert_eq!(guard.version(), 3);
+    assert_eq!(splitwrite::ReadGuard::map(guard, |x| x).version(), 3);
+}
+
+#[test]
+fn enter_at_least_waits_for_the_writer() {
+    let (mut w, r) = splitwrite::new::<Applied<i32>, Apply<i32>>();
+    let factory = r.factory();
+    let reader = thread::spawn(move || {
+        let r = factory.handle();
+        let guard = r.enter_at_least(3).unwrap();
+        assert!(guard.version() >= 3);
+        assert!(**guard >= 2);
+    });
+
+    w.publish();
+    for _ in 0..2 {
+        w.apply(|x| *x += 1);
+        w.publish();
+    }
+    reader.join().unwrap();
+}
@@ -1,8 +1,8 @@
 #[cfg(loom)]
 #[cfg(test)]
 mod loom_tests {
-    
-    use left_right::Absorb;
+
+    use splitwrite::Absorb;
     include!("../src/utilities.rs");
 
     use loom::thread;
@@ -10,7 +10,7 @@ mod loom_tests {
     #[test]
     fn read_before_publish() {
         loom::model(|| {
-            let (mut w, r) = left_right::new::<i32, _>();
+            let (mut w, r) = splitwrite::new::<i32, _>();
 
             w.append(CounterAddOp(1));
             w.publish();
@@ -25,4 +25,33 @@ mod loom_tests {
             assert_eq!(1, val);
         });
     }
+
+    #[test]
+    fn register_during_publish() {
+        loom::model(|| {
+            let (mut w, r) = splitwrite::new::<i32, _>();
+            w.append(CounterAddOp(1));
+            w.publish();
+
+            let factory = r.factory();
+            let jh = thread::spawn(move || {
+                // registers a new slot while the writer may be publishing.
+                let r = factory.handle();
+                let guard = r.enter().unwrap();
+                let before = *guard;
+                thread::yield_now();
+                // the writer must not have written to the copy we are in.
+                assert_eq!(before, *guard);
+                before
+            });
+
+            w.append(CounterAddOp(1));
+            w.publish();
+            w.append(CounterAddOp(1));
+            w.publish();
+
+            let val = jh.join().unwrap();
+            assert!((1..=3).contains(&val));
+        });
+    }
 }
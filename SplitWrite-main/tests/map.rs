@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+struct Value {
+    v: i32,
+    live: Arc<AtomicI64>,
+}
+
+impl Value {
+    fn new(v: i32, live: &Arc<AtomicI64>) -> Self {
+        live.fetch_add(1, Ordering::SeqCst);
+        Self {
+            v,
+            live: Arc::clone(live),
+        }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        let was = self.live.fetch_sub(1, Ordering::SeqCst);
+        assert!(was > 0);
+    }
+}
+
+#[test]
+fn map() {
+    let (mut w, r) = splitwrite::map::new();
+
+    w.insert(1, "a").insert(2, "b");
+    assert!(r.is_empty());
+    assert_eq!(r.get(&1).map(|v| *v), None);
+
+    w.publish();
+    assert_eq!(r.len(), 2);
+    assert_eq!(r.get(&1).map(|v| *v), Some("a"));
+    assert!(r.contains_key(&2));
+
+    w.insert(1, "c").remove(2);
+    w.publish();
+    assert_eq!(r.len(), 1);
+    assert_eq!(r.get(&1).map(|v| *v), Some("c"));
+    assert!(!r.contains_key(&2));
+
+    // both copies must have seen the same operations.
+    w.publish();
+    let mut entries: Vec<_> = r.enter().unwrap().iter().map(|(&k, &v)| (k, v)).collect();
+    entries.sort();
+    assert_eq!(entries, [(1, "c")]);
+
+    drop(w);
+    assert!(r.was_dropped());
+    assert_eq!(r.get(&1).map(|v| *v), None);
+}
+
+#[test]
+fn retain_and_clear() {
+    let (mut w, r) = splitwrite::map::new();
+    for i in 0..10 {
+        w.insert(i, i * 10);
+    }
+    w.publish();
+
+    w.retain(|_, v| v % 20 == 0);
+    w.publish();
+    assert_eq!(r.len(), 5);
+    assert!(r.enter().unwrap().values().all(|v| v % 20 == 0));
+
+    w.publish();
+    assert_eq!(r.len(), 5);
+
+    w.clear();
+    w.publish();
+    assert!(r.is_empty());
+    w.publish();
+    assert!(r.is_empty());
+}
+
+#[test]
+fn values_dropped_once() {
+    let live = Arc::new(AtomicI64::new(0));
+    let expect = |n| assert_eq!(live.load(Ordering::SeqCst), n);
+
+    let (mut w, r) = splitwrite::map::new();
+
+    // operations before the first publish go straight into the write copy.
+    w.insert("a", Value::new(1, &live));
+    w.insert("b", Value::new(2, &live));
+    w.publish();
+    expect(2);
+
+    w.insert("c", Value::new(3, &live));
+    w.publish();
+    expect(3);
+    assert_eq!(r.get("c").map(|v| v.v), Some(3));
+
+    // a replaced value stays alive until both copies have let go of it.
+    w.insert("a", Value::new(4, &live));
+    w.publish();
+    expect(4);
+    w.publish();
+    expect(3);
+    assert_eq!(r.get("a").map(|v| v.v), Some(4));
+
+    w.remove("b");
+    w.retain(|k, _| *k != "c");
+    w.publish();
+    expect(3);
+    w.publish();
+    expect(1);
+
+    w.insert("d", Value::new(5, &live));
+    w.clear();
+    w.publish();
+    w.publish();
+    expect(0);
+
+    w.insert("e", Value::new(6, &live));
+    w.insert("f", Value::new(7, &live));
+    drop(w);
+    expect(0);
+    drop(r);
+}
+
+#[test]
+fn rollback_drops_pending_values() {
+    let live = Arc::new(AtomicI64::new(0));
+    let (mut w, r) = splitwrite::map::new();
+    w.insert("a", Value::new(1, &live));
+    w.publish();
+
+    w.insert("a", Value::new(2, &live))
+        .insert("b", Value::new(3, &live))
+        .remove("a");
+    assert_eq!(live.load(Ordering::SeqCst), 3);
+    w.rollback();
+    assert_eq!(live.load(Ordering::SeqCst), 1);
+
+    w.publish().publish();
+    assert_eq!(r.get("a").map(|v| v.v), Some(1));
+    assert!(!r.contains_key("b"));
+    drop(w);
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn concurrent_readers() {
+    let (mut w, r) = splitwrite::map::new();
+    w.insert(0usize, 0usize);
+    w.publish();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let r = r.clone();
+            std::thread::spawn(move || {
+                let mut last = 0;
+                while last < 100 {
+                    let Some(map) = r.enter() else {
+                        break;
+                    };
+                    let v = *map.get(&0).unwrap();
+                    assert!(v >= last);
+                    // every published map holds all keys up to the current value.
+                    assert_eq!(map.len(), v + 1);
+                    last = v;
+                }
+            })
+        })
+        .collect();
+
+    for i in 1..=100 {
+        w.insert(i, i).insert(0, i);
+        w.publish();
+    }
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+}
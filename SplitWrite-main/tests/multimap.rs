@@ -0,0 +1,143 @@
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+struct Value {
+    v: i32,
+    live: Arc<AtomicI64>,
+}
+
+impl Value {
+    fn new(v: i32, live: &Arc<AtomicI64>) -> Self {
+        live.fetch_add(1, Ordering::SeqCst);
+        Self {
+            v,
+            live: Arc::clone(live),
+        }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        let was = self.live.fetch_sub(1, Ordering::SeqCst);
+        assert!(was > 0);
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.v == other.v
+    }
+}
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.v.hash(state)
+    }
+}
+
+#[test]
+fn multimap() {
+    let (mut w, r) = splitwrite::multimap::new();
+
+    w.insert(1, "a").insert(1, "b").insert(2, "c");
+    w.publish();
+    assert_eq!(r.len(), 2);
+    assert_eq!(r.get(&1).map(|vs| vs.len()), Some(2));
+    assert_eq!(r.get_one(&1).map(|v| *v), Some("a"));
+    assert!(r.get(&1).unwrap().contains(&"b"));
+
+    w.remove_value(1, "a");
+    w.empty(2);
+    w.publish();
+    assert_eq!(r.get(&1).unwrap().iter().collect::<Vec<_>>(), [&"b"]);
+    assert!(r.contains_key(&2));
+    assert!(r.get(&2).unwrap().is_empty());
+    assert_eq!(r.get_one(&2).map(|v| *v), None);
+
+    w.remove_entry(2);
+    w.publish();
+    assert!(!r.contains_key(&2));
+
+    // both copies must have seen the same operations.
+    w.publish();
+    assert_eq!(r.len(), 1);
+    assert_eq!(r.get(&1).unwrap().iter().collect::<Vec<_>>(), [&"b"]);
+}
+
+#[test]
+fn large_bags() {
+    let (mut w, r) = splitwrite::multimap::new();
+    for i in 0..100 {
+        w.insert("k", i % 50);
+    }
+    w.publish();
+
+    let count = |v| r.get("k").unwrap().iter().filter(|&&x| x == v).count();
+    assert_eq!(r.get("k").unwrap().len(), 100);
+    assert_eq!(count(7), 2);
+
+    w.remove_value("k", 7);
+    w.publish();
+    assert_eq!(r.get("k").unwrap().len(), 99);
+    assert_eq!(count(7), 1);
+
+    w.remove_value("k", 7);
+    w.publish();
+    w.publish();
+    assert_eq!(r.get("k").unwrap().len(), 98);
+    assert!(!r.get("k").unwrap().contains(&7));
+}
+
+#[test]
+fn values_dropped_once() {
+    let live = Arc::new(AtomicI64::new(0));
+    let expect = |n| assert_eq!(live.load(Ordering::SeqCst), n);
+
+    let (mut w, r) = splitwrite::multimap::new();
+    w.insert("a", Value::new(1, &live));
+    w.publish();
+    expect(1);
+
+    // small bags keep equal values as separate instances.
+    w.insert("a", Value::new(1, &live));
+    w.publish();
+    w.publish();
+    expect(2);
+    assert_eq!(r.get("a").unwrap().len(), 2);
+
+    // growing into a large bag only keeps one instance of equal values.
+    for i in 0..40 {
+        w.insert("b", Value::new(i % 20, &live));
+    }
+    w.publish();
+    expect(42);
+    w.publish();
+    expect(22);
+    assert_eq!(r.get("b").unwrap().len(), 40);
+
+    // as does adding a duplicate to a large bag.
+    w.insert("b", Value::new(3, &live));
+    w.publish();
+    w.publish();
+    expect(22);
+    assert_eq!(r.get("b").unwrap().iter().filter(|v| v.v == 3).count(), 3);
+
+    w.remove_value("b", Value::new(3, &live));
+    w.remove_value("a", Value::new(1, &live));
+    w.publish();
+    w.publish();
+    expect(21);
+    assert_eq!(r.get_one("a").map(|v| v.v), Some(1));
+
+    w.empty("a");
+    w.remove_entry("b");
+    w.publish();
+    w.publish();
+    expect(0);
+
+    w.insert("c", Value::new(1, &live));
+    drop(w);
+    expect(0);
+}
@@ -0,0 +1,164 @@
+#![cfg(feature = "replication")]
+
+use serde::{Deserialize, Serialize};
+use splitwrite::replication::{self, Leader};
+use splitwrite::Absorb;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Op {
+    Set(u32, String),
+    Remove(u32),
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+struct Map(BTreeMap<u32, String>);
+
+impl Absorb<Op> for Map {
+    fn absorb_first(&mut self, operation: &mut Op, _: &Self) {
+        match operation {
+            Op::Set(k, v) => {
+                self.0.insert(*k, v.clone());
+            }
+            Op::Remove(k) => {
+                self.0.remove(k);
+            }
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+fn set(k: u32) -> Op {
+    Op::Set(k, k.to_string())
+}
+
+#[test]
+fn late_followers_catch_up_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (w, r) = splitwrite::new::<Map, Op>();
+    let mut leader = Leader::new(w);
+    for k in 0..5 {
+        leader.append(set(k)).publish().unwrap();
+    }
+
+    let follower = thread::spawn(move || {
+        let (mut follower, r) =
+            replication::follow::<Map, Op, _>(TcpStream::connect(addr).unwrap()).unwrap();
+        assert_eq!(follower.position(), 5);
+        assert_eq!(r.enter().unwrap().0.len(), 5);
+        follower.run().unwrap();
+        assert_eq!(follower.position(), 8);
+        let data = r.enter().unwrap().0.clone();
+        drop(follower);
+        data
+    });
+    leader.add_follower(listener.accept().unwrap().0).unwrap();
+    leader
+        .append(Op::Remove(0))
+        .append(set(5))
+        .publish()
+        .unwrap();
+    leader.append(set(6));
+    leader.publish().unwrap();
+    // empty publishes are sent too.
+    leader.publish().unwrap();
+    assert_eq!(leader.position(), 8);
+    let expected = r.enter().unwrap().0.clone();
+    assert_eq!(expected, (1..=6).map(|k| (k, k.to_string())).collect());
+    drop(leader);
+
+    assert_eq!(follower.join().unwrap(), expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn followers_publish_at_the_same_boundaries() {
+    use std::os::unix::net::UnixStream;
+
+    let (w, r) = splitwrite::new::<Map, Op>();
+    let mut leader = Leader::new(w);
+    let (ours, theirs) = UnixStream::pair().unwrap();
+    let id = leader.add_follower(ours).unwrap();
+    let (mut follower, fr) = replication::follow::<Map, Op, _>(theirs).unwrap();
+    assert_eq!(leader.followers(), 1);
+
+    leader.extend([set(1), set(2)]);
+    leader.publish().unwrap();
+    leader.append(set(3)).rollback();
+    leader.publish().unwrap();
+    leader.append(Op::Remove(1)).publish().unwrap();
+
+    let version = fr.enter().unwrap().version();
+    assert!(follower.apply_next().unwrap());
+    assert_eq!(fr.enter().unwrap().version(), version + 1);
+    assert_eq!(fr.enter().unwrap().0.keys().collect::<Vec<_>>(), [&1, &2]);
+    assert!(follower.apply_next().unwrap());
+    assert_eq!(fr.enter().unwrap().version(), version + 2);
+    assert!(follower.apply_next().unwrap());
+    assert_eq!(fr.enter().unwrap().0, r.enter().unwrap().0);
+    assert_eq!(fr.enter().unwrap().version(), version + 3);
+
+    // a follower that goes away is dropped once its thread fails to write to it.
+    drop((follower, fr));
+    let dropped = loop {
+        let dropped = leader.append(set(4)).publish().unwrap();
+        if !dropped.is_empty() {
+            break dropped;
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(dropped, [id]);
+    assert_eq!(leader.followers(), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn followers_that_fall_behind_are_dropped() {
+    use std::os::unix::net::UnixStream;
+
+    let (w, _r) = splitwrite::new::<Map, Op>();
+    let mut leader = Leader::new(w);
+    leader.set_max_backlog(4);
+    let (ours, theirs) = UnixStream::pair().unwrap();
+    let id = leader.add_follower(ours).unwrap();
+
+    // nobody reads `theirs`, so once the socket buffer is full, batches pile up in the queue
+    // until it overflows, without publish ever blocking.
+    let value = "x".repeat(1 << 16);
+    let dropped = (0..10_000)
+        .map(|k| {
+            leader
+                .append(Op::Set(k % 8, value.clone()))
+                .publish()
+                .unwrap()
+        })
+        .find(|dropped| !dropped.is_empty())
+        .expect("the follower was never dropped");
+    assert_eq!(dropped, [id]);
+    assert_eq!(leader.followers(), 0);
+    let position = leader.position();
+
+    // what was queued before the overflow still arrives, and then the stream ends cleanly.
+    let (mut follower, _) = replication::follow::<Map, Op, _>(theirs).unwrap();
+    follower.run().unwrap();
+    assert!(follower.position() < position);
+}
+
+#[test]
+fn followers_reject_other_streams() {
+    let mut stream = Vec::new();
+    stream.write_all(b"SWRP\x02\x00\x00\x00\x00").unwrap();
+    let err = replication::follow::<Map, Op, _>(&stream[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let err = replication::follow::<Map, Op, _>(&b"HTTP/1.1 200 OK"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
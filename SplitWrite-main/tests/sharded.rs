@@ -0,0 +1,97 @@
+use splitwrite::sharded::{Keyed, Sharded};
+use splitwrite::Absorb;
+use std::collections::HashMap;
+use std::thread;
+
+#[derive(Debug)]
+struct Add(u64);
+
+impl Keyed for Add {
+    type Key = u64;
+    fn key(&self) -> &u64 {
+        &self.0
+    }
+}
+
+#[derive(Clone, Default)]
+struct Counts(HashMap<u64, usize>);
+
+impl Absorb<Add> for Counts {
+    fn absorb_first(&mut self, operation: &mut Add, _: &Self) {
+        *self.0.entry(operation.0).or_default() += 1;
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+fn by_key(key: &u64) -> usize {
+    *key as usize
+}
+
+#[test]
+fn ops_go_to_their_shard() {
+    let (mut w, r) = Sharded::<Counts, Add, _>::new(4, by_key);
+    w.publish();
+    w.extend((0..10).map(Add));
+    w.append(Add(5));
+    assert!(w.has_pending_operations());
+    assert!(r.enter(1).unwrap().0.is_empty());
+
+    w.publish();
+    assert!(!w.has_pending_operations());
+    for key in 0..10 {
+        assert_eq!(r.shard_of(&key), w.shard_of(&key));
+        let shard = r.enter_key(&key).unwrap();
+        let expected = if key == 5 { 2 } else { 1 };
+        assert_eq!(shard.0.get(&key), Some(&expected));
+    }
+    let total: usize = r.enter_all().unwrap().iter().map(|s| s.0.len()).sum();
+    assert_eq!(total, 10);
+}
+
+#[test]
+fn shards_publish_independently() {
+    let (mut w, r) = Sharded::<Counts, Add, _>::new(2, by_key);
+    w.append(Add(0)).append(Add(1));
+    w.publish_shard(1);
+    assert!(r.enter(0).unwrap().0.is_empty());
+    assert_eq!(r.enter(1).unwrap().0.get(&1), Some(&1));
+    assert_eq!(r.shard(0).generation(), 0);
+
+    w.publish();
+    assert_eq!(r.enter(0).unwrap().0.get(&0), Some(&1));
+}
+
+#[test]
+fn shards_publish_from_separate_threads() {
+    let (mut w, r) = Sharded::<Counts, Add, _>::new(4, by_key);
+    thread::scope(|s| {
+        for (i, shard) in w.shards_mut().iter_mut().enumerate() {
+            s.spawn(move || {
+                for _ in 0..100 {
+                    shard.append(Add(i as u64)).publish();
+                }
+            });
+        }
+    });
+    for i in 0..4 {
+        assert_eq!(r.enter(i).unwrap().0.get(&(i as u64)), Some(&100));
+    }
+
+    drop(w);
+    assert!(r.was_dropped());
+    assert!(r.enter_all().is_none());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "appended to shard 1 directly")]
+fn misrouted_ops_are_caught() {
+    let (mut w, _r) = Sharded::<Counts, Add, _>::new(2, by_key);
+    w.publish();
+    // key 0 belongs in shard 0.
+    w.shard_mut(1).append(Add(0));
+    w.append(Add(1));
+}
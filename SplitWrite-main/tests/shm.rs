@@ -0,0 +1,120 @@
+#![cfg(feature = "shm")]
+
+use splitwrite::shm::{self, ReadHandle};
+use splitwrite::{Applied, Apply};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// A counter repeated in every element, so that a torn read shows up as a mismatch.
+type Counters = Applied<[u64; 16]>;
+
+fn bump(c: &mut [u64; 16]) {
+    for x in c {
+        *x += 1;
+    }
+}
+
+/// Run the test named `name` in a child process, with `path` to read from.
+fn child(name: &str, path: &Path) -> Command {
+    let mut cmd = Command::new(env::current_exe().unwrap());
+    cmd.args([name, "--exact", "--nocapture"])
+        .env("SPLITWRITE_SHM_CHILD", path);
+    cmd
+}
+
+#[test]
+fn readers_see_published_copies() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counters");
+    let (mut w, r) = shm::create::<_, Apply<[u64; 16]>, _>(&path, Applied([0; 16]), 3).unwrap();
+    assert_eq!(r.enter().unwrap().0, [0; 16]);
+
+    w.append(Apply::new(bump));
+    assert!(w.has_pending_operations());
+    assert_eq!(r.enter().unwrap().0[0], 0);
+    w.publish();
+    let guard = r.enter().unwrap();
+    assert_eq!(guard.0[0], 1);
+
+    let other = ReadHandle::<Counters>::open(&path).unwrap();
+    assert_eq!(other.enter().unwrap().0[0], 1);
+    let _third = other.try_clone().unwrap();
+    assert!(ReadHandle::<Counters>::open(&path).is_err());
+    assert!(ReadHandle::<u8>::open(&path).is_err());
+
+    // the guard is in the copy that the publish after next would write.
+    w.append(Apply::new(bump)).publish();
+    w.append(Apply::new(bump));
+    assert!(w.try_publish().is_err());
+    assert_eq!(guard.0, [1; 16]);
+    drop(guard);
+    w.publish();
+    assert_eq!(other.enter().unwrap().0, [3; 16]);
+
+    drop(w);
+    assert!(r.was_dropped());
+    assert!(other.enter().is_none());
+}
+
+#[test]
+fn readers_in_other_processes() {
+    const PUBLISHES: u64 = 200;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counters");
+    let (mut w, _r) = shm::create::<_, Apply<[u64; 16]>, _>(&path, Applied([0; 16]), 4).unwrap();
+    let mut reader = child("reader_process", &path).spawn().unwrap();
+    for _ in 0..PUBLISHES {
+        w.append(Apply::new(bump)).publish();
+    }
+    // tell the child we are done.
+    w.append(Apply::new(|c: &mut [u64; 16]| *c = [u64::MAX; 16]))
+        .publish();
+    assert!(reader.wait().unwrap().success());
+}
+
+#[test]
+fn reader_process() {
+    let Some(path) = env::var_os("SPLITWRITE_SHM_CHILD") else {
+        return;
+    };
+    let r = ReadHandle::<Counters>::open(path).unwrap();
+    let mut last = 0;
+    loop {
+        let c = *r.enter().unwrap();
+        assert!(c.0.iter().all(|&x| x == c.0[0]), "torn read: {c:?}");
+        if c.0[0] == u64::MAX {
+            break;
+        }
+        assert!(c.0[0] >= last);
+        last = c.0[0];
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn readers_that_die_mid_read_are_not_waited_for() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counters");
+    let (mut w, r) = shm::create::<_, Apply<[u64; 16]>, _>(&path, Applied([0; 16]), 2).unwrap();
+    let status = child("dying_reader_process", &path).status().unwrap();
+    assert!(!status.success());
+
+    for _ in 0..3 {
+        w.append(Apply::new(bump)).publish();
+    }
+    assert_eq!(r.enter().unwrap().0, [3; 16]);
+    // and its slot can be reused.
+    assert!(ReadHandle::<Counters>::open(&path).is_ok());
+}
+
+#[test]
+fn dying_reader_process() {
+    let Some(path) = env::var_os("SPLITWRITE_SHM_CHILD") else {
+        return;
+    };
+    let r = ReadHandle::<Counters>::open(path).unwrap();
+    let _guard = r.enter().unwrap();
+    std::process::abort();
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "serde")]
+
+use splitwrite::{Applied, Apply};
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read};
+
+type Map = BTreeMap<String, u32>;
+
+#[test]
+fn restores_the_published_data() {
+    let (mut w, r) = splitwrite::new::<Applied<Map>, Apply<Map>>();
+    w.apply(|m| {
+        m.insert("a".into(), 1);
+    });
+    w.publish();
+    w.apply(|m| {
+        m.insert("b".into(), 2);
+    });
+
+    let mut buf = Vec::new();
+    assert_eq!(r.snapshot_to(&mut buf).unwrap(), 1);
+    // other things may follow the snapshot in the same stream.
+    buf.extend_from_slice(b"rest");
+
+    let mut reader = Cursor::new(buf);
+    let (mut w2, r2) =
+        splitwrite::restore_from::<Applied<Map>, Apply<Map>, _>(&mut reader).unwrap();
+    assert_eq!(**r2.enter().unwrap(), BTreeMap::from([("a".into(), 1)]));
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "rest");
+
+    w2.apply(|m| *m.get_mut("a").unwrap() += 10);
+    w2.publish();
+    assert_eq!(r2.enter().unwrap()["a"], 11);
+    drop(w);
+    assert!(r.snapshot_to(io::sink()).is_err());
+}
+
+#[test]
+fn damaged_snapshots_are_rejected() {
+    let (mut w, r) = splitwrite::new::<Applied<Map>, Apply<Map>>();
+    w.apply(|m| {
+        m.insert("key".into(), 7);
+    });
+    w.publish();
+    let mut buf = Vec::new();
+    r.snapshot_to(&mut buf).unwrap();
+
+    let at = buf.len() - 8;
+    buf[at] ^= 1;
+    let err = splitwrite::restore_from::<Applied<Map>, Apply<Map>, _>(&buf[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let err = splitwrite::restore_from::<Applied<Map>, Apply<Map>, _>(&b"SWAL"[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
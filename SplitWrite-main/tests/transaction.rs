@@ -0,0 +1,188 @@
+use splitwrite::{Absorb, Preview};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+enum Op {
+    Insert(&'static str, u32),
+    Remove(&'static str),
+}
+
+#[derive(Clone, Default)]
+struct Map(HashMap<&'static str, u32>);
+
+impl Absorb<Op> for Map {
+    fn absorb_first(&mut self, operation: &mut Op, _: &Self) {
+        match *operation {
+            Op::Insert(k, v) => {
+                self.0.insert(k, v);
+            }
+            Op::Remove(k) => {
+                self.0.remove(k);
+            }
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+impl Preview<Op> for Map {
+    fn preview(&mut self, operation: &Op) {
+        match *operation {
+            Op::Insert(k, v) => {
+                self.0.insert(k, v);
+            }
+            Op::Remove(k) => {
+                self.0.remove(k);
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Exists(&'static str);
+
+/// Insert every pair, but only if none of the keys exist yet.
+fn insert_new(
+    w: &mut splitwrite::WriteHandle<Map, Op>,
+    pairs: &[(&'static str, u32)],
+) -> Result<(), Exists> {
+    w.transaction(|tx| {
+        for &(k, v) in pairs {
+            if tx.current().0.contains_key(k) {
+                return Err(Exists(k));
+            }
+            tx.append(Op::Insert(k, v));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn insert_if_absent() {
+    let (mut w, r) = splitwrite::new::<Map, Op>();
+    w.publish();
+    assert_eq!(insert_new(&mut w, &[("a", 1), ("b", 2)]), Ok(()));
+    assert_eq!(insert_new(&mut w, &[("c", 3), ("b", 4)]), Err(Exists("b")));
+    w.publish();
+    assert_eq!(r.enter().unwrap().0, HashMap::from([("a", 1), ("b", 2)]));
+
+    w.append(Op::Remove("a"));
+    assert_eq!(insert_new(&mut w, &[("a", 5), ("a", 6)]), Err(Exists("a")));
+    // the aborted transaction left nothing behind for the next one to see.
+    w.transaction(|tx| {
+        assert_eq!(tx.current().0, HashMap::from([("b", 2)]));
+        assert_eq!(tx.published().0.len(), 2);
+        Ok::<_, ()>(())
+    })
+    .unwrap();
+    assert_eq!(insert_new(&mut w, &[("a", 5), ("d", 7)]), Ok(()));
+    w.publish();
+    assert_eq!(
+        r.enter().unwrap().0,
+        HashMap::from([("a", 5), ("b", 2), ("d", 7)])
+    );
+}
+
+#[test]
+fn aborted_transactions_keep_earlier_pending_operations() {
+    let (mut w, r) = splitwrite::new::<Map, Op>();
+    w.append(Op::Insert("a", 1)).publish();
+    w.append(Op::Insert("b", 2));
+    let result: Result<(), _> = w.transaction(|tx| {
+        tx.extend([Op::Remove("a"), Op::Remove("b")]);
+        assert_eq!(tx.pending().count(), 3);
+        Err("no")
+    });
+    assert_eq!(result, Err("no"));
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _: Result<(), ()> = w.transaction(|tx| {
+            tx.append(Op::Remove("b"));
+            panic!("halfway");
+        });
+    }));
+    assert!(outcome.is_err());
+
+    w.publish();
+    assert_eq!(r.enter().unwrap().0, HashMap::from([("a", 1), ("b", 2)]));
+}
+
+#[test]
+fn transactions_before_the_first_publish() {
+    let (mut w, r) = splitwrite::new::<Map, Op>();
+    w.append(Op::Insert("a", 1));
+    assert_eq!(insert_new(&mut w, &[("b", 2), ("a", 3)]), Err(Exists("a")));
+    assert_eq!(insert_new(&mut w, &[("b", 2), ("c", 3)]), Ok(()));
+    w.transaction(|tx| {
+        assert_eq!(tx.pending().count(), 0);
+        tx.append(Op::Remove("c"));
+        assert_eq!(tx.pending().count(), 1);
+        assert_eq!(tx.current().0, HashMap::from([("a", 1), ("b", 2)]));
+        assert!(tx.published().0.is_empty());
+        Err::<(), _>("changed my mind")
+    })
+    .unwrap_err();
+
+    w.publish();
+    assert_eq!(
+        r.enter().unwrap().0,
+        HashMap::from([("a", 1), ("b", 2), ("c", 3)])
+    );
+}
+
+/// An operation that counts how often it is absorbed, and as which copy.
+struct Counted(Arc<[AtomicUsize; 2]>);
+
+#[derive(Clone, Default)]
+struct Total(usize);
+
+impl Absorb<Counted> for Total {
+    fn absorb_first(&mut self, operation: &mut Counted, _: &Self) {
+        operation.0[0].fetch_add(1, Ordering::SeqCst);
+        self.0 += 1;
+    }
+
+    fn absorb_second(&mut self, operation: Counted, _: &Self) {
+        operation.0[1].fetch_add(1, Ordering::SeqCst);
+        self.0 += 1;
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0 = first.0;
+    }
+}
+
+impl Preview<Counted> for Total {
+    fn preview(&mut self, _: &Counted) {
+        self.0 += 1;
+    }
+}
+
+#[test]
+fn current_does_not_absorb() {
+    let (mut w, r) = splitwrite::new::<Total, Counted>();
+    w.publish();
+    let counts: Vec<_> = (0..3).map(|_| Arc::<[AtomicUsize; 2]>::default()).collect();
+    w.transaction(|tx| {
+        for (i, count) in counts.iter().enumerate() {
+            tx.append(Counted(Arc::clone(count)));
+            assert_eq!(tx.current().0, i + 1);
+        }
+        assert_eq!(tx.current().0, 3);
+        Ok::<_, ()>(())
+    })
+    .unwrap();
+
+    // one publish absorbs each operation into one copy, and the next into the other.
+    w.publish().publish();
+    assert_eq!(r.enter().unwrap().0, 3);
+    for count in &counts {
+        assert_eq!(count[0].load(Ordering::SeqCst), 1);
+        assert_eq!(count[1].load(Ordering::SeqCst), 1);
+    }
+}
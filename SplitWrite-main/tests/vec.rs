@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+struct Value {
+    v: i32,
+    live: Arc<AtomicI64>,
+}
+
+impl Value {
+    fn new(v: i32, live: &Arc<AtomicI64>) -> Self {
+        live.fetch_add(1, Ordering::SeqCst);
+        Self {
+            v,
+            live: Arc::clone(live),
+        }
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        let was = self.live.fetch_sub(1, Ordering::SeqCst);
+        assert!(was > 0);
+    }
+}
+
+#[test]
+fn vec() {
+    let (mut w, r) = splitwrite::vec::new();
+    w.extend(0..5);
+    w.publish();
+    assert_eq!(&*r.enter().unwrap(), &[0, 1, 2, 3, 4]);
+
+    w.push(5).set(0, 10).swap_remove(1).pop();
+    assert_eq!(w.pending_len(), 4);
+    w.publish();
+    assert_eq!(&*r.enter().unwrap(), &[10, 5, 2, 3]);
+    assert_eq!(r.get(1).map(|v| *v), Some(5));
+    assert_eq!(r.get(4).map(|v| *v), None);
+
+    w.truncate(2).truncate(3);
+    w.publish();
+    assert_eq!(&*r.enter().unwrap(), &[10, 5]);
+
+    // both copies must have seen the same operations.
+    w.publish();
+    assert_eq!(&*r.enter().unwrap(), &[10, 5]);
+    assert_eq!(r.len(), 2);
+
+    w.pop().pop().pop();
+    w.publish();
+    assert!(r.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn set_out_of_bounds() {
+    let (mut w, _r) = splitwrite::vec::new();
+    w.push(1);
+    w.publish();
+    w.swap_remove(0);
+    w.set(0, 2);
+}
+
+#[test]
+fn values_dropped_once() {
+    let live = Arc::new(AtomicI64::new(0));
+    let expect = |n| assert_eq!(live.load(Ordering::SeqCst), n);
+
+    let (mut w, r) = splitwrite::vec::new();
+    w.extend((0..4).map(|i| Value::new(i, &live)));
+    w.publish();
+    expect(4);
+
+    w.push(Value::new(4, &live));
+    w.set(0, Value::new(5, &live));
+    w.publish();
+    expect(6);
+    w.publish();
+    expect(5);
+    assert_eq!(r.get(0).map(|v| v.v), Some(5));
+
+    w.swap_remove(0).pop().truncate(1);
+    w.publish();
+    expect(5);
+    w.publish();
+    expect(1);
+    assert_eq!(
+        r.enter().unwrap().iter().map(|v| v.v).collect::<Vec<_>>(),
+        [4]
+    );
+
+    w.push(Value::new(6, &live));
+    drop(w);
+    expect(0);
+}
+
+#[test]
+fn rollback_drops_pending_values() {
+    let live = Arc::new(AtomicI64::new(0));
+    let (mut w, r) = splitwrite::vec::new();
+    w.push(Value::new(1, &live));
+    w.publish();
+
+    w.push(Value::new(2, &live))
+        .extend([Value::new(3, &live), Value::new(4, &live)]);
+    w.set(0, Value::new(5, &live)).pop();
+    assert_eq!(w.pending_len(), 3);
+    assert_eq!(live.load(Ordering::SeqCst), 5);
+
+    w.rollback();
+    assert_eq!(live.load(Ordering::SeqCst), 1);
+    assert_eq!(w.pending_len(), 1);
+    assert!(!w.has_pending_operations());
+
+    w.push(Value::new(6, &live));
+    w.publish();
+    assert_eq!(r.get(0).map(|v| v.v), Some(1));
+    assert_eq!(r.get(1).map(|v| v.v), Some(6));
+    drop(w);
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn rollback_before_the_first_publish_keeps_the_length() {
+    let (mut w, r) = splitwrite::vec::new();
+    w.push(1).push(2);
+    // these were absorbed right away, so there is nothing to roll back.
+    w.rollback();
+    assert_eq!(w.pending_len(), 2);
+    w.publish();
+    assert_eq!(&*r.enter().unwrap(), &[1, 2]);
+
+    w.set(0, 9).pop();
+    w.publish();
+    assert_eq!(&*r.enter().unwrap(), &[9]);
+}
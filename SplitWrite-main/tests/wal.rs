@@ -0,0 +1,159 @@
+#![cfg(feature = "wal")]
+
+use serde::{Deserialize, Serialize};
+use splitwrite::wal::{self, SyncPolicy};
+use splitwrite::Absorb;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Op {
+    Push(u32),
+    Clear,
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+struct List(Vec<u32>);
+
+impl Absorb<Op> for List {
+    fn absorb_first(&mut self, operation: &mut Op, _: &Self) {
+        match *operation {
+            Op::Push(x) => self.0.push(x),
+            Op::Clear => self.0.clear(),
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.0.clone_from(&first.0);
+    }
+}
+
+#[test]
+fn recovers_published_and_pending_operations() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("list.wal");
+
+    let (mut w, r) = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    assert!(r.enter().unwrap().0.is_empty());
+    w.extend([Op::Push(1), Op::Push(2)]).unwrap();
+    w.publish().unwrap();
+    w.append(Op::Clear).unwrap().append(Op::Push(3)).unwrap();
+    w.publish().unwrap();
+    w.append(Op::Push(4)).unwrap().rollback().unwrap();
+    w.append(Op::Push(5)).unwrap();
+    let savepoint = w.savepoint();
+    w.append(Op::Push(6))
+        .unwrap()
+        .rollback_to(savepoint)
+        .unwrap();
+    let aborted: Result<(), ()> = w
+        .transaction(|tx| {
+            tx.append(Op::Push(7));
+            Err(())
+        })
+        .unwrap();
+    assert!(aborted.is_err());
+    let committed: Result<(), ()> = w
+        .transaction(|tx| {
+            tx.append(Op::Push(8));
+            Ok(())
+        })
+        .unwrap();
+    assert!(committed.is_ok());
+    // the process dies without publishing, or dropping the handle.
+    std::mem::forget(w);
+    drop(r);
+
+    let (mut w, r) = wal::open::<List, Op, _>(&path, SyncPolicy::Never).unwrap();
+    assert_eq!(r.enter().unwrap().0, [3]);
+    assert!(w.has_pending_operations());
+    w.publish().unwrap();
+    assert_eq!(r.enter().unwrap().0, [3, 5, 8]);
+    w.append(Op::Push(9)).unwrap();
+    w.sync().unwrap();
+    // dropping the handle publishes, and logs that it did.
+    drop((w, r));
+
+    let (w, r) = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(r.enter().unwrap().0, [3, 5, 8, 9]);
+    assert!(!w.has_pending_operations());
+}
+
+#[test]
+fn torn_records_are_cut_off() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("list.wal");
+
+    let (mut w, _) = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    w.append(Op::Push(1)).unwrap().publish().unwrap();
+    w.append(Op::Push(2)).unwrap();
+    let intact = fs::metadata(&path).unwrap().len();
+    w.publish().unwrap();
+    drop(w);
+
+    // the process died halfway through logging the second publish.
+    let file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(fs::metadata(&path).unwrap().len() - 1)
+        .unwrap();
+    drop(file);
+
+    let (mut w, r) = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(r.enter().unwrap().0, [1]);
+    assert_eq!(fs::metadata(&path).unwrap().len(), intact);
+    assert!(w.has_pending_operations());
+    w.rollback().unwrap();
+    w.append(Op::Push(3)).unwrap().publish().unwrap();
+    drop((w, r));
+
+    // and here it wrote garbage instead.
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[4, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8])
+        .unwrap();
+    drop(file);
+
+    let (_w, r) = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(r.enter().unwrap().0, [1, 3]);
+}
+
+#[test]
+fn other_files_are_not_logs() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::write(&path, "not a log at all").unwrap();
+
+    let err = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fs::read(&path).unwrap(), b"not a log at all");
+}
+
+#[test]
+fn checkpoints_empty_the_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("list.wal");
+
+    let (mut w, r) = wal::restore::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    for i in 0..10 {
+        w.append(Op::Push(i)).unwrap().publish().unwrap();
+    }
+    w.append(Op::Push(10)).unwrap();
+    let before = fs::read(&path).unwrap();
+    w.checkpoint().unwrap();
+    assert!(fs::metadata(&path).unwrap().len() < before.len() as u64);
+    w.publish().unwrap();
+    drop((w, r));
+
+    let err = wal::open::<List, Op, _>(&path, SyncPolicy::Always).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let (mut w, r) = wal::restore::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(r.enter().unwrap().0, (0..=10).collect::<Vec<_>>());
+    w.append(Op::Clear).unwrap().append(Op::Push(11)).unwrap();
+    w.publish().unwrap();
+    drop((w, r));
+
+    // a crash after the snapshot was written, but before the log was emptied.
+    fs::write(&path, before).unwrap();
+    let (mut w, r) = wal::restore::<List, Op, _>(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(r.enter().unwrap().0, (0..10).collect::<Vec<_>>());
+    w.publish().unwrap();
+    assert_eq!(r.enter().unwrap().0, (0..=10).collect::<Vec<_>>());
+}